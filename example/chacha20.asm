@@ -50,11 +50,12 @@ chacha20:
     blt _end
 
     push .sizeof.key
+    muli 4
     loadf key.addr
-    push
-    jal read_stdin_packed
+    muli 4
+    push .fd.stdin
+    ecall .cc.read_packed
     storef retval
-    addsp -2
     loadf retval
     push 0
     blt _end
@@ -70,11 +71,12 @@ chacha20:
     blt _end
 
     push .sizeof.nonce
+    muli 4
     loadf nonce.addr
-    push
-    jal read_stdin_packed
+    muli 4
+    push .fd.stdin
+    ecall .cc.read_packed
     storef retval
-    addsp -2
     loadf retval
     push 0
     blt _end
@@ -122,9 +124,7 @@ chacha20:
     push .sizeof.state
     loadf state.addr
     loadf orig_state.addr
-    push
-    jal memcpy
-    addsp -4
+    memcpy
 
     ; perform rounds
     push 0
@@ -188,109 +188,6 @@ chacha20:
     ret
 
 
-read_stdin_packed:
-    .param out.addr 1
-    .param out.len 1
-    .local buf 128
-    .local buf.addr 1
-    .local buf.len 1
-    .local word 1
-    .local i 1
-    .start_frame
-
-    loadf out.len
-    muli 4
-    storef buf.len
-
-    loadf buf.len
-    push .sizeof.buf
-    bgt _too_long
-
-    loadi fp
-    addi buf
-    storef buf.addr
-
-    loadf buf.len
-    loadf buf.addr
-    push .fd.stdin
-    ecall .cc.read
-    storef retval
-    loadf retval
-    push 0
-    blt _end
-
-    ; for (i = 0; i < sizeof(buf); i += 4)
-    push 0
-    storef i
-    _loop:
-        push 0
-        storef word
-
-        ; word |= (buf[i] << 24);
-        loadf buf.addr
-        loadf i
-        add
-        load
-        shli 24
-        loadf word
-        or
-        storef word
-
-        ; word |= (buf[i+1] << 16);
-        loadf buf.addr
-        loadf i
-        addi 1
-        add
-        load
-        shli 16
-        loadf word
-        or
-        storef word
-
-        ; word |= (buf[i+2] << 8);
-        loadf buf.addr
-        loadf i
-        addi 2
-        add
-        load
-        shli 8
-        loadf word
-        or
-        storef word
-
-        ; word |= buf[i+3];
-        loadf buf.addr
-        loadf i
-        addi 3
-        add
-        load
-        loadf word
-        or
-        storef word
-
-        ; out[i / 4] = word;
-        loadf word
-        loadf out.addr
-        loadf i
-        divi 4
-        add
-        store
-
-        loadf i
-        addi 4
-        storef i
-
-        loadf i
-        loadf buf.len
-        blt _loop
-    jump _end
-    _too_long:
-        push -5
-        storef retval
-    _end:
-        .end_frame
-        ret
-
 double_round:
     .param state.addr 1
     .start_frame
@@ -464,55 +361,17 @@ qqround: ;(i32*,i32*,i32*,i32)
     loadf z
     store
 
-    ; *z = (*z << rotamt) | (*z >> rotamt)
-    loadf z
-    load
-    loadf rotamt
-    shl
+    ; *z = rotl(*z, rotamt)
     loadf z
     load
     loadf rotamt
-    push 32
-    sub
-    shr
-    or
+    rotl
     loadf z
     store
 
     .end_frame
     ret
 
-memcpy:
-    .param dst.addr 1
-    .param src.addr 1
-    .param len 1
-    .local i 1
-    .start_frame
-
-    push 0
-    storef i
-    _loop:
-        loadf src.addr
-        loadf i
-        add
-        load
-
-        loadf dst.addr
-        loadf i
-        add
-        store
-
-        loadf i
-        addi 1
-        storef i
-
-        loadf i
-        loadf len
-        blt _loop
-
-    .end_frame
-    ret
-
 PROMPT.KEY:
     .string "KEY>" 0x20 ; space
 
@@ -521,6 +380,6 @@ PROMPT.NONCE:
 
 STATE.CONST:
     .word "expa"
-    .word "nd" 0x20 "3"
+    .word 0x6e642033 ; "nd 3"
     .word "2-by"
-    .word "te" 0x20 "k"
+    .word 0x7465206b ; "te k"
@@ -0,0 +1,108 @@
+.define NHANDLERS 3
+
+main:
+    push
+    jal dispatch_all
+    ecall .cc.exit
+
+; calls every handler in HANDLERS through a function pointer
+dispatch_all:
+    .local table.addr 1
+    .local handler 1
+    .local i 1
+    .start_frame
+
+    loadi pc
+    addi 2, HANDLERS
+    storef table.addr
+
+    push 0
+    storef i
+    _loop:
+        loadf table.addr
+        loadf i
+        add
+        load
+        storef handler
+
+        .call lf:handler lf:i
+
+        loadf i
+        addi 1
+        storef i
+
+        loadf i
+        push NHANDLERS
+        blt _loop
+
+    push 0
+    storef retval
+    .end_frame
+    ret
+
+say_hello:
+    .param n 1
+    .start_frame
+    .call print_str p:.L.MSG.HELLO.start p:.L.MSG.HELLO.len
+    .call describe lf:n
+    .end_frame
+    ret
+
+say_bye:
+    .param n 1
+    .start_frame
+    .call print_str p:.L.MSG.BYE.start p:.L.MSG.BYE.len
+    .call describe lf:n
+    .end_frame
+    ret
+
+; prints a description of n, using a switch table of inner labels
+describe:
+    .param n 1
+    .start_frame
+
+    loadf n
+    push 2
+    bge _default
+
+    loadi pc
+    addi 2, _cases
+    loadf n
+    add
+    load
+    jumpr
+    _cases:
+        .word _zero _one
+    _zero:
+        .call print_str p:.L.MSG.ZERO.start p:.L.MSG.ZERO.len
+        jump _end
+    _one:
+        .call print_str p:.L.MSG.ONE.start p:.L.MSG.ONE.len
+        jump _end
+    _default:
+        .call print_str p:.L.MSG.MANY.start p:.L.MSG.MANY.len
+    _end:
+    .end_frame
+    ret
+
+print_str:
+    .param str.addr 1
+    .param str.len 1
+    .start_frame
+    .call env.write p:.fd.stdout lf:str.addr lf:str.len
+    .end_frame
+    ret
+
+HANDLERS:
+    .word say_hello say_bye say_hello
+
+MSG.HELLO:
+    .string "hello" 0x20
+MSG.BYE:
+    .string "bye" 0x20
+MSG.ZERO:
+    .string "zero" 0x0a
+MSG.ONE:
+    .string "one" 0x0a
+MSG.MANY:
+    .string "many" 0x0a
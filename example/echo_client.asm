@@ -0,0 +1,40 @@
+; Sends a line to echo_server.asm and prints what comes back, run with `--allow-net`.
+main:
+    push
+    jal ping
+    ecall .cc.exit
+
+ping:
+    .local conn 1
+    .local buf 64
+    .addr_of buf
+    .local n 1
+    .start_frame
+
+    .call env.connect p:.L.ADDR.start p:.L.ADDR.len ret:conn
+    loadf conn
+    push 0
+    blt _fail
+
+    .call env.send lf:conn p:.L.MSG.start p:.L.MSG.len
+    .call env.recv lf:conn lf:buf.addr p:.sizeof.buf ret:n
+    loadf n
+    push 0
+    ble _fail
+    .call env.write p:.fd.stdout lf:buf.addr lf:n
+    .call env.close lf:conn
+
+    push 0
+    storef retval
+    jump _end
+    _fail:
+    push 1
+    storef retval
+    _end:
+    .end_frame
+    ret
+
+ADDR:
+    .string "127.0.0.1:7878"
+MSG:
+    .string "ping" 0x0a
@@ -0,0 +1,47 @@
+; Echoes back everything sent over one TCP connection on localhost:7878,
+; run with `--allow-net`. echo_client.asm talks to it.
+main:
+    push
+    jal serve
+    ecall .cc.exit
+
+serve:
+    .local listener 1
+    .local conn 1
+    .local buf 64
+    .addr_of buf
+    .local n 1
+    .start_frame
+
+    .call env.socket_listen p:.L.ADDR.start p:.L.ADDR.len ret:listener
+    loadf listener
+    push 0
+    blt _fail
+
+    .call env.accept lf:listener ret:conn
+    loadf conn
+    push 0
+    blt _fail
+
+    _echo:
+        .call env.recv lf:conn lf:buf.addr p:.sizeof.buf ret:n
+        loadf n
+        push 0
+        ble _done
+        .call env.send lf:conn lf:buf.addr lf:n
+        jump _echo
+    _done:
+    .call env.close lf:conn
+    .call env.close lf:listener
+    push 0
+    storef retval
+    jump _end
+    _fail:
+    push 1
+    storef retval
+    _end:
+    .end_frame
+    ret
+
+ADDR:
+    .string "127.0.0.1:7878"
@@ -0,0 +1,35 @@
+; Greets whoever is named by its first argument, spawned by spawn.asm.
+main:
+    push
+    jal greet
+    ecall .cc.exit
+
+greet:
+    .local name 64
+    .addr_of name
+    .local name.len 1
+    .start_frame
+
+    .call env.argc ret:retval
+    loadf retval
+    push 2
+    bne _usage
+
+    .call env.argv p:1 lf:name.addr p:.sizeof.name ret:name.len
+    .call env.write p:.fd.stdout p:.L.HELLO.start p:.L.HELLO.len
+    .call env.write p:.fd.stdout lf:name.addr lf:name.len
+    push 0x0a
+    storef name, 0
+    .call env.write p:.fd.stdout lf:name.addr p:1
+    push 0
+    storef retval
+    jump _end
+    _usage:
+    push 2
+    storef retval
+    _end:
+    .end_frame
+    ret
+
+HELLO:
+    .string "hello," 0x20
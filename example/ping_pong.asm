@@ -0,0 +1,108 @@
+; Message passing between two machines, run with `--machines 2`:
+; machine 0 sends 1..=N to machine 1, which sends back their sum.
+.define N 10
+.define DONE -1
+
+main:
+    push
+    jal ping_pong
+    ecall .cc.exit
+
+ping_pong:
+    .local id 1
+    .start_frame
+
+    .call env.machine_id ret:id
+
+    loadf id
+    push 0
+    beq _sender
+    loadf id
+    push 1
+    beq _summer
+    push 1 ; not enough machines or too many
+    storef retval
+    jump _end
+    _sender:
+    .call send_numbers ret:retval
+    jump _end
+    _summer:
+    .call sum_numbers ret:retval
+    _end:
+    .end_frame
+    ret
+
+send_numbers:
+    .local i 1
+    .local total 1
+    .start_frame
+
+    push 1
+    storef i
+    _loop:
+        .call env.msg_send p:1 lf:i
+
+        loadf i
+        addi 1
+        storef i
+
+        loadf i
+        push N
+        ble _loop
+
+    .call env.msg_send p:1 p:DONE
+    .call env.msg_recv ret:total
+
+    loadf total
+    push 55 ; N * (N + 1) / 2
+    beq _ok
+    .call print_str p:.L.MSG.FAIL.start p:.L.MSG.FAIL.len
+    push 1
+    storef retval
+    jump _end
+    _ok:
+    .call print_str p:.L.MSG.OK.start p:.L.MSG.OK.len
+    push 0
+    storef retval
+    _end:
+    .end_frame
+    ret
+
+sum_numbers:
+    .local x 1
+    .local sum 1
+    .start_frame
+
+    push 0
+    storef sum
+    _loop:
+        .call env.msg_recv ret:x
+
+        loadf x
+        push DONE
+        beq _done
+
+        loadf sum
+        loadf x
+        add
+        storef sum
+        jump _loop
+    _done:
+    .call env.msg_send p:0 lf:sum
+    push 0
+    storef retval
+    .end_frame
+    ret
+
+print_str:
+    .param str.addr 1
+    .param str.len 1
+    .start_frame
+    .call env.write p:.fd.stdout lf:str.addr lf:str.len
+    .end_frame
+    ret
+
+MSG.OK:
+    .string "sum" 0x20 "ok" 0x0a
+MSG.FAIL:
+    .string "sum" 0x20 "wrong" 0x0a
@@ -0,0 +1,101 @@
+; A producer/consumer pipeline on green threads: the producer sends 1..=N
+; through a channel, the consumer sums them up and returns the total.
+.define N 10
+.define DONE -1
+
+main:
+    push
+    jal run_pipeline
+    ecall .cc.exit
+
+run_pipeline:
+    .local chan 1
+    .local producer 1
+    .local consumer 1
+    .local total 1
+    .start_frame
+
+    .call env.chan_new p:4 ret:chan
+    .call env.thread_spawn p:.L.produce.start lf:chan ret:producer
+    .call env.thread_spawn p:.L.consume.start lf:chan ret:consumer
+
+    .call env.thread_join lf:producer
+    .call env.thread_join lf:consumer ret:total
+
+    loadf total
+    push 55 ; N * (N + 1) / 2
+    beq _ok
+    .call print_str p:.L.MSG.FAIL.start p:.L.MSG.FAIL.len
+    push 1
+    storef retval
+    jump _end
+    _ok:
+    .call print_str p:.L.MSG.OK.start p:.L.MSG.OK.len
+    push 0
+    storef retval
+    _end:
+    .end_frame
+    ret
+
+produce:
+    .param chan 1
+    .local i 1
+    .start_frame
+
+    push 1
+    storef i
+    _loop:
+        .call env.chan_send lf:chan lf:i
+
+        loadf i
+        addi 1
+        storef i
+
+        loadf i
+        push N
+        ble _loop
+
+    .call env.chan_send lf:chan p:DONE
+    push 0
+    storef retval
+    .end_frame
+    ret
+
+consume:
+    .param chan 1
+    .local x 1
+    .local sum 1
+    .start_frame
+
+    push 0
+    storef sum
+    _loop:
+        .call env.chan_recv lf:chan ret:x
+
+        loadf x
+        push DONE
+        beq _done
+
+        loadf sum
+        loadf x
+        add
+        storef sum
+        jump _loop
+    _done:
+    loadf sum
+    storef retval
+    .end_frame
+    ret
+
+print_str:
+    .param str.addr 1
+    .param str.len 1
+    .start_frame
+    .call env.write p:.fd.stdout lf:str.addr lf:str.len
+    .end_frame
+    ret
+
+MSG.OK:
+    .string "sum" 0x20 "ok" 0x0a
+MSG.FAIL:
+    .string "sum" 0x20 "wrong" 0x0a
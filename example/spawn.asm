@@ -0,0 +1,66 @@
+; Runs greet.asm as a child program with its output going through a pipe,
+; copies that output to stdout and checks the child's exit code.
+; Run it from the repository root.
+main:
+    push
+    jal run_child
+    ecall .cc.exit
+
+run_child:
+    .local fds 2
+    .addr_of fds
+    .local argv 2
+    .addr_of argv
+    .local buf 16
+    .addr_of buf
+    .local read_fd 1
+    .local write_fd 1
+    .local n 1
+    .local pid 1
+    .start_frame
+
+    .call env.pipe lf:fds.addr
+    loadf fds, 0
+    storef read_fd
+    loadf fds, 1
+    storef write_fd
+
+    push .L.NAME.start
+    storef argv, 0
+    push .L.NAME.len
+    storef argv, 1
+
+    ; child's stdout is the write end of the pipe
+    .call env.spawn p:.L.PATH.start p:.L.PATH.len lf:argv.addr p:1 p:.fd.stdin lf:write_fd ret:pid
+    loadf pid
+    push 0
+    blt _fail
+
+    ; close our copy of the write end so we see end of file once the child exits
+    .call env.close lf:write_fd
+
+    _copy:
+        .call env.read lf:read_fd lf:buf.addr p:.sizeof.buf ret:n
+        loadf n
+        push 0
+        ble _copied
+        .call env.write p:.fd.stdout lf:buf.addr lf:n
+        jump _copy
+    _copied:
+
+    .call env.wait lf:pid ret:retval
+    loadf retval
+    push 0
+    bne _fail
+    jump _end
+    _fail:
+    push 1
+    storef retval
+    _end:
+    .end_frame
+    ret
+
+PATH:
+    .string "example/greet.asm"
+NAME:
+    .string "world"
@@ -46,9 +46,7 @@ sum_rows:
         loadi pc
         addi 2, PRESET_PATH
         loadf path.addr
-        push
-        jal memcpy
-        addsp -4
+        memcpy
     _path_done:
     .call env.open lf:path.addr lf:path.len ret:fd
 
@@ -80,13 +78,20 @@ sum_rows:
 
     .call env.malloc lf:ncols ret:sums.addr
 
-    .for i 0 to ncols ; init sums loop
+    push 0
+    storef i
+    _init_sums: ; init sums loop
         push 0
         loadf sums.addr
         loadf i
         add
         store
-    .end_for
+        loadf i
+        addi 1
+        storef i
+        loadf i
+        loadf ncols
+        blt _init_sums
     loadf buf.addr
     storef buf.ptr
     _sum_loop:
@@ -108,7 +113,9 @@ sum_rows:
 
         .call read_row lf:buf.ptr lf:line_len lf:row.addr
 
-        .for i 0 to ncols ; row sum loop
+        push 0
+        storef i
+        _row_sum: ; row sum loop
             ; x = dec_to_int(row[i*2], row[i*2+1])
             loadf row.addr
             loadf i
@@ -129,17 +136,23 @@ sum_rows:
             loadf sums.addr
             loadf i
             add
+            dup
             load
             loadf x
             add
-            loadf sums.addr
-            loadf i
-            add
+            swap
             store
-        .end_for
+            loadf i
+            addi 1
+            storef i
+            loadf i
+            loadf ncols
+            blt _row_sum
         jump _sum_loop
     _sum_done:
-    .for i 0 to ncols ; print loop
+    push 0
+    storef i
+    _print: ; print loop
         loadf columns.addr
         loadf i
         muli 2
@@ -151,9 +164,7 @@ sum_rows:
         loadf col.addr
         load 0 ; ptr
         loadf path.addr
-        push
-        jal memcpy
-        addsp -4
+        memcpy
 
         loadf col.addr
         load 1 ; len
@@ -193,7 +204,12 @@ sum_rows:
         storef path.len
 
         .call env.write p:.fd.stdout lf:path.addr lf:path.len
-    .end_for
+        loadf i
+        addi 1
+        storef i
+        loadf i
+        loadf ncols
+        blt _print
     _ok:
     push 0
     storef retval
@@ -211,7 +227,9 @@ dec_to_int:
     push 0
     storef retval
 
-    .for i 0 to decimal.len
+    push 0
+    storef i
+    _digits:
         loadf retval
         muli 10
         storef retval
@@ -228,7 +246,12 @@ dec_to_int:
         loadf retval
         add
         storef retval
-    .end_for
+        loadf i
+        addi 1
+        storef i
+        loadf i
+        loadf decimal.len
+        blt _digits
 
     .end_frame
     ret
@@ -237,6 +260,8 @@ int_to_dec:
     .param x 1
     .param dst.addr 1 ; at least size 11
     .local i 1
+    .local j 1
+    .local k 1
     .start_frame
 
     push 0
@@ -262,6 +287,44 @@ int_to_dec:
         loadf x
         push 0
         bne _loop
+
+    ; the digits came out least significant first, reverse them
+    push 0
+    storef j
+    loadf i
+    subi 1
+    storef k
+    _reverse:
+        loadf j
+        loadf k
+        bge _reversed
+
+        ; dst[j], dst[k] = dst[k], dst[j]
+        loadf dst.addr
+        loadf j
+        add
+        load
+        loadf dst.addr
+        loadf k
+        add
+        load
+        loadf dst.addr
+        loadf j
+        add
+        store
+        loadf dst.addr
+        loadf k
+        add
+        store
+
+        loadf j
+        addi 1
+        storef j
+        loadf k
+        subi 1
+        storef k
+        jump _reverse
+    _reversed:
     loadf i
     storef retval
     .end_frame
@@ -389,7 +452,9 @@ num_columns:
     push 1
     storef n
 
-    .for i 0 to str.len
+    push 0
+    storef i
+    _count:
         loadf str.addr
         loadf i
         add
@@ -402,7 +467,12 @@ num_columns:
         storef n
 
         _next:
-    .end_for
+        loadf i
+        addi 1
+        storef i
+        loadf i
+        loadf str.len
+        blt _count
 
     loadf n
     storef retval
@@ -414,37 +484,6 @@ num_columns:
     .end_frame
     ret
 
-memcpy:
-    .param dst.addr 1
-    .param src.addr 1
-    .param len 1
-    .local i 1
-    .start_frame
-
-    push 0
-    storef i
-    _loop:
-        loadf src.addr
-        loadf i
-        add
-        load
-
-        loadf dst.addr
-        loadf i
-        add
-        store
-
-        loadf i
-        addi 1
-        storef i
-
-        loadf i
-        loadf len
-        blt _loop
-
-    .end_frame
-    ret
-
 read_path_from_stdin:
     .param path.addr 1
     .param path.len 1
@@ -69,9 +69,7 @@ sum_rows:
         loadi pc
         addi 2, PRESET_PATH
         loadf path.addr
-        push
-        jal memcpy
-        addsp -4
+        memcpy
     _path_done:
     .call env.open lf:path.addr lf:path.len ret:fd
 ; BEGIN {{
@@ -146,26 +144,20 @@ sum_rows:
     storef sums.addr
 ; }} END
 
-    .for i 0 to ncols ; init sums loop
-; BEGIN {{
     push 0
     storef i
-    _loop.0:
-; }} END
+    _init_sums: ; init sums loop
         push 0
         loadf sums.addr
         loadf i
         add
         store
-    .end_for
-; BEGIN {{
-    loadf i
-    addi 1
-    storef i
-    loadf i
-    loadf ncols
-    blt _loop.0
-; }} END
+        loadf i
+        addi 1
+        storef i
+        loadf i
+        loadf ncols
+        blt _init_sums
     loadf buf.addr
     storef buf.ptr
     _sum_loop:
@@ -204,12 +196,9 @@ sum_rows:
     addsp -4
 ; }} END
 
-        .for i 0 to ncols ; row sum loop
-; BEGIN {{
-    push 0
-    storef i
-    _loop.1:
-; }} END
+        push 0
+        storef i
+        _row_sum: ; row sum loop
             ; x = dec_to_int(row[i*2], row[i*2+1])
             loadf row.addr
             loadf i
@@ -230,30 +219,23 @@ sum_rows:
             loadf sums.addr
             loadf i
             add
+            dup
             load
             loadf x
             add
-            loadf sums.addr
-            loadf i
-            add
+            swap
             store
-        .end_for
-; BEGIN {{
-    loadf i
-    addi 1
-    storef i
-    loadf i
-    loadf ncols
-    blt _loop.1
-; }} END
+            loadf i
+            addi 1
+            storef i
+            loadf i
+            loadf ncols
+            blt _row_sum
         jump _sum_loop
     _sum_done:
-    .for i 0 to ncols ; print loop
-; BEGIN {{
     push 0
     storef i
-    _loop.2:
-; }} END
+    _print: ; print loop
         loadf columns.addr
         loadf i
         muli 2
@@ -265,9 +247,7 @@ sum_rows:
         loadf col.addr
         load 0 ; ptr
         loadf path.addr
-        push
-        jal memcpy
-        addsp -4
+        memcpy
 
         loadf col.addr
         load 1 ; len
@@ -314,15 +294,12 @@ sum_rows:
     ecall .cc.write
     addsp -1
 ; }} END
-    .end_for
-; BEGIN {{
-    loadf i
-    addi 1
-    storef i
-    loadf i
-    loadf ncols
-    blt _loop.2
-; }} END
+        loadf i
+        addi 1
+        storef i
+        loadf i
+        loadf ncols
+        blt _print
     _ok:
     push 0
     storef retval
@@ -350,12 +327,9 @@ dec_to_int:
     push 0
     storef retval
 
-    .for i 0 to decimal.len
-; BEGIN {{
     push 0
     storef i
-    _loop.0:
-; }} END
+    _digits:
         loadf retval
         muli 10
         storef retval
@@ -372,15 +346,12 @@ dec_to_int:
         loadf retval
         add
         storef retval
-    .end_for
-; BEGIN {{
-    loadf i
-    addi 1
-    storef i
-    loadf i
-    loadf decimal.len
-    blt _loop.0
-; }} END
+        loadf i
+        addi 1
+        storef i
+        loadf i
+        loadf decimal.len
+        blt _digits
 
     .end_frame
 ; BEGIN {{
@@ -393,12 +364,14 @@ int_to_dec:
     .param x 1
     .param dst.addr 1 ; at least size 11
     .local i 1
+    .local j 1
+    .local k 1
     .start_frame
 ; BEGIN {{
     loadi fp
     loadi sp
     storei fp
-    addsp 1
+    addsp 3
 ; }} END
 
     push 0
@@ -424,11 +397,49 @@ int_to_dec:
         loadf x
         push 0
         bne _loop
+
+    ; the digits came out least significant first, reverse them
+    push 0
+    storef j
+    loadf i
+    subi 1
+    storef k
+    _reverse:
+        loadf j
+        loadf k
+        bge _reversed
+
+        ; dst[j], dst[k] = dst[k], dst[j]
+        loadf dst.addr
+        loadf j
+        add
+        load
+        loadf dst.addr
+        loadf k
+        add
+        load
+        loadf dst.addr
+        loadf j
+        add
+        store
+        loadf dst.addr
+        loadf k
+        add
+        store
+
+        loadf j
+        addi 1
+        storef j
+        loadf k
+        subi 1
+        storef k
+        jump _reverse
+    _reversed:
     loadf i
     storef retval
     .end_frame
 ; BEGIN {{
-    addsp -1
+    addsp -3
     storei fp
 ; }} END
     ret
@@ -581,12 +592,9 @@ num_columns:
     push 1
     storef n
 
-    .for i 0 to str.len
-; BEGIN {{
     push 0
     storef i
-    _loop.0:
-; }} END
+    _count:
         loadf str.addr
         loadf i
         add
@@ -599,15 +607,12 @@ num_columns:
         storef n
 
         _next:
-    .end_for
-; BEGIN {{
-    loadf i
-    addi 1
-    storef i
-    loadf i
-    loadf str.len
-    blt _loop.0
-; }} END
+        loadf i
+        addi 1
+        storef i
+        loadf i
+        loadf str.len
+        blt _count
 
     loadf n
     storef retval
@@ -623,47 +628,6 @@ num_columns:
 ; }} END
     ret
 
-memcpy:
-    .param dst.addr 1
-    .param src.addr 1
-    .param len 1
-    .local i 1
-    .start_frame
-; BEGIN {{
-    loadi fp
-    loadi sp
-    storei fp
-    addsp 1
-; }} END
-
-    push 0
-    storef i
-    _loop:
-        loadf src.addr
-        loadf i
-        add
-        load
-
-        loadf dst.addr
-        loadf i
-        add
-        store
-
-        loadf i
-        addi 1
-        storef i
-
-        loadf i
-        loadf len
-        blt _loop
-
-    .end_frame
-; BEGIN {{
-    addsp -1
-    storei fp
-; }} END
-    ret
-
 read_path_from_stdin:
     .param path.addr 1
     .param path.len 1
@@ -6,7 +6,8 @@ use std::{error, io};
 use AssemblyError::*;
 use ParserError::*;
 
-use crate::linker::{DebugInfo, Linker, LinkerError, TargetTerm};
+use crate::encoder;
+use crate::linker::{DebugInfo, Linker, LinkerError, RelocationTarget, TargetTerm};
 use crate::mem::addrs;
 
 #[derive(Debug)]
@@ -112,11 +113,15 @@ impl Assembler {
         self.linker.add_global_constant("sp", addrs::SP);
         self.linker.add_global_constant("fp", addrs::FP);
         self.linker.add_global_constant("retval", -3);
-        for (callcode, (_, call_name)) in crate::environment::CALL_LIST.iter().enumerate() {
+        for (callcode, (_, call_name, _)) in crate::environment::CALL_LIST.iter().enumerate() {
             let const_name = format!(".cc.{}", call_name);
             self.linker
                 .add_global_constant(&const_name, callcode as i32);
         }
+        for (name, code) in crate::machine::FAULT_CODES {
+            self.linker
+                .add_global_constant(&format!(".fault.{}", name), *code);
+        }
         self.linker.add_global_constant(".fd.stdin", 1);
         self.linker.add_global_constant(".fd.stdout", 1);
         self.linker.add_global_constant(".fd.stderr", 2);
@@ -351,14 +356,25 @@ impl Assembler {
                     nargs - 1
                 ),
             };
+            let jump_code = match call_target.strip_prefix("lf:") {
+                // call through a function pointer held in a frame variable
+                Some(ptr_var) => format!(
+                    "
+                    loadf {}
+                    jalr
+                ",
+                    ptr_var
+                ),
+                None => format!("jal {}", call_target),
+            };
             write!(
                 code,
                 "
                 push
-                jal {call_target}
+                {jump_code}
                 {epilogue}
             ",
-                call_target = call_target,
+                jump_code = jump_code,
                 epilogue = epilogue
             )
             .unwrap();
@@ -372,23 +388,40 @@ impl Assembler {
         }
         let mut bytes: Vec<u8> = vec![];
         for arg in args {
-            if let Some(val) = Assembler::parse_hex_i32(arg) {
-                self.linker.add_raw_word(val);
-            } else if let Ok(val) = Assembler::expect_int_literal(arg) {
-                bytes.push(val as u8);
-            } else if let Ok(string) = Assembler::expect_string_literal(arg) {
+            // numbers are whole words, only strings are packed four bytes to a word
+            match Assembler::expect_int_literal(arg) {
+                Ok(val) => {
+                    self.add_packed_bytes(&bytes);
+                    bytes.clear();
+                    self.linker.add_raw_word(val);
+                    continue;
+                }
+                Err(_NotAnInteger) => {}
+                Err(err) => return Err(err),
+            }
+            if let Ok(string) = Assembler::expect_string_literal(arg) {
                 bytes.extend(string.bytes());
+            } else if let Ok(target) = Assembler::expect_word_target(arg) {
+                // label addresses and constants, relocated to absolute values
+                self.add_packed_bytes(&bytes);
+                bytes.clear();
+                self.linker.add_placeholder_word(target);
             } else {
                 return Err(SyntaxError(
-                    ".word expects int or string literals".to_string(),
+                    ".word expects int or string literals, or identifiers".to_string(),
                 ));
             }
         }
+        self.add_packed_bytes(&bytes);
+        Ok(())
+    }
+
+    fn add_packed_bytes(&mut self, bytes: &[u8]) {
         let nbytes = bytes.len();
         if nbytes == 0 {
-            return Ok(());
+            return;
         }
-        let num_words = cmp::max(1, nbytes / 4);
+        let num_words = nbytes.div_ceil(4);
         for i in 0..num_words {
             let end = cmp::min(nbytes, (i + 1) * 4);
             let word_bytes = &bytes[i * 4..end];
@@ -398,7 +431,6 @@ impl Assembler {
             }
             self.linker.add_raw_word(word);
         }
-        Ok(())
     }
 
     fn process_instruction(&mut self, op_name: &str, args: &[&str]) -> Result<(), ParserError> {
@@ -422,22 +454,50 @@ impl Assembler {
             ));
         }
         let target: Vec<_> = terms.into_iter().map(|r| r.unwrap()).collect();
+        if let Some(value) = Assembler::literal_value(&target) {
+            if !encoder::ARG_RANGE.contains(&value) {
+                return self.process_wide_immediate(op_name, value);
+            }
+        }
         self.linker.add_placeholder_inst(op_name, target);
         Ok(())
     }
 
-    fn parse_hex_i32(arg: &str) -> Option<i32> {
-        if !arg.starts_with("0x") {
-            return None;
-        }
-        let arg = &arg[2..];
-        if arg.len() != 8 {
-            return None;
-        }
-        match u32::from_str_radix(arg, 16) {
-            Ok(val) => Some(val as i32),
-            Err(_) => None,
-        }
+    fn literal_value(target: &[TargetTerm]) -> Option<i32> {
+        target.iter().try_fold(0i32, |sum, term| match term {
+            TargetTerm::Literal(x) => Some(sum.wrapping_add(*x)),
+            TargetTerm::Ident(_) => None,
+        })
+    }
+
+    /// Builds immediates that don't fit the 24-bit argument field with `pushhi` and `orlo`,
+    /// followed by the register form of the op if it has one. Register forms apply their own
+    /// immediate too, so they get the one that leaves the result alone.
+    fn process_wide_immediate(&mut self, op_name: &str, value: i32) -> Result<(), ParserError> {
+        let reg_op = match op_name {
+            "push" => "",
+            "addi" => "add 0",
+            "muli" => "mul 1",
+            "andi" => "and -1",
+            "ori" => "or 0",
+            "xori" => "xor 0",
+            _ => {
+                return Err(SyntaxError(format!(
+                    "immediate does not fit in 24 bits: {} {}",
+                    op_name, value
+                )))
+            }
+        };
+        self.process_internal(&format!(
+            "
+            pushhi {hi}
+            orlo {lo}
+            {reg_op}
+        ",
+            hi = value >> 16,
+            lo = value & 0xffff,
+            reg_op = reg_op
+        ))
     }
 
     fn expect_ident(arg: &str) -> Result<&str, ParserError> {
@@ -463,6 +523,26 @@ impl Assembler {
         Ok(arg)
     }
 
+    /// A `.word` target is one or more terms joined by `+`, e.g. `handlers+2`,
+    /// where at least one term is an identifier.
+    fn expect_word_target(arg: &str) -> Result<RelocationTarget, ParserError> {
+        let mut target = RelocationTarget::new();
+        for term in arg.split('+') {
+            match Assembler::expect_int_literal(term) {
+                Ok(literal) => target.push(TargetTerm::Literal(literal)),
+                Err(_NotAnInteger) => {
+                    let ident = Assembler::expect_ident(term)?;
+                    target.push(TargetTerm::Ident(ident.to_string()));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        if !target.iter().any(|t| matches!(t, TargetTerm::Ident(_))) {
+            return Err(SyntaxError(format!("expected identifier: {}", arg)));
+        }
+        Ok(target)
+    }
+
     fn expect_string_literal(arg: &str) -> Result<&str, ParserError> {
         if arg.len() < 2 || &arg[0..1] != "\"" || &arg[arg.len() - 1..] != "\"" {
             return Err(SyntaxError(format!("invalid string literal: {}", arg)));
@@ -473,8 +553,9 @@ impl Assembler {
 
     fn expect_int_literal(arg: &str) -> Result<i32, ParserError> {
         if arg.starts_with("0x") {
-            return match i32::from_str_radix(&arg[2..], 16) {
-                Ok(arg) => Ok(arg),
+            // hex literals are bit patterns, so 0xdeadbeef is as valid as 0x7fffffff
+            return match u32::from_str_radix(&arg[2..], 16) {
+                Ok(arg) => Ok(arg as i32),
                 Err(err) => Err(InvalidIntLiteral(err)),
             };
         }
@@ -486,9 +567,24 @@ impl Assembler {
             chars.next();
             return Ok(chars.next().unwrap() as i32);
         }
+        if let Some(bits) = Assembler::parse_float(arg) {
+            return Ok(bits);
+        }
         Err(_NotAnInteger)
     }
 
+    /// Float literals have an `f` suffix, e.g. `1.5f` or `-2e3f`, and evaluate to their bit pattern.
+    fn parse_float(arg: &str) -> Option<i32> {
+        let num = arg.strip_suffix('f')?;
+        if !num.starts_with(|ch: char| ch.is_ascii_digit() || ch == '-' || ch == '.') {
+            return None;
+        }
+        match num.parse::<f32>() {
+            Ok(val) => Some(val.to_bits() as i32),
+            Err(_) => None,
+        }
+    }
+
     fn expect_ident_and_int<'a>(
         verb: &'a str,
         args: &'a [&'a str],
@@ -509,3 +605,42 @@ impl Assembler {
         format!(".sizeof.{}", var_name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The words a `.word` directive emits, assembled after a minimal `main`.
+    fn word(args: &str) -> Result<Vec<i32>, AssemblyError> {
+        let source = format!("main:\n    ecall .cc.exit\nDATA:\n    .word {}\n", args);
+        let end = assemble_from_source(source.as_bytes())?.binary;
+        let data_start = assemble_from_source(&b"main:\n    ecall .cc.exit\n"[..])
+            .unwrap()
+            .binary
+            .len();
+        Ok(end[data_start..].to_vec())
+    }
+
+    #[test]
+    fn word_numbers_are_whole_words() {
+        assert_eq!(
+            word("300 0x1234 -1 0xdeadbeef 'a'").unwrap(),
+            vec![300, 0x1234, -1, 0xdeadbeef_u32 as i32, 'a' as i32]
+        );
+        assert_eq!(word("1.5f").unwrap(), vec![1.5f32.to_bits() as i32]);
+    }
+
+    #[test]
+    fn word_strings_are_packed() {
+        assert_eq!(
+            word("\"abcde\" 7 \"fg\"").unwrap(),
+            vec![0x61626364, 0x65000000, 7, 0x66670000]
+        );
+    }
+
+    #[test]
+    fn word_rejects_bad_numbers() {
+        assert!(word("0x1g").is_err());
+        assert!(word("0x123456789").is_err());
+    }
+}
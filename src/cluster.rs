@@ -0,0 +1,100 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::machine::MachineStatus::*;
+use crate::machine::{Machine, MachineError};
+
+/// Messages a machine's mailbox holds before senders have to wait.
+pub const MAILBOX_CAPACITY: usize = 16;
+
+/// Cycles each machine runs for before the cluster moves on to the next one.
+pub const SLICE: usize = 100;
+
+pub struct Mailboxes {
+    queues: Vec<VecDeque<i32>>,
+}
+
+impl Mailboxes {
+    pub fn is_full(&self, id: usize) -> bool {
+        self.queues[id].len() >= MAILBOX_CAPACITY
+    }
+
+    pub fn send(&mut self, id: usize, val: i32) {
+        self.queues[id].push_back(val);
+    }
+
+    pub fn recv(&mut self, id: usize) -> Option<i32> {
+        self.queues[id].pop_front()
+    }
+
+    pub fn num_machines(&self) -> usize {
+        self.queues.len()
+    }
+}
+
+/// A machine's connection to the rest of its cluster, held by its environment.
+#[derive(Clone)]
+pub struct ClusterLink {
+    pub id: usize,
+    pub mailboxes: Rc<RefCell<Mailboxes>>,
+}
+
+/// Runs several machines in one process, exchanging words through bounded mailboxes.
+/// Machines take turns in id order, so runs are deterministic.
+pub struct Cluster {
+    pub machines: Vec<Machine>,
+}
+
+impl Cluster {
+    pub fn new(mut machines: Vec<Machine>) -> Cluster {
+        let mailboxes = Rc::new(RefCell::new(Mailboxes {
+            queues: vec![VecDeque::new(); machines.len()],
+        }));
+        for (id, machine) in machines.iter_mut().enumerate() {
+            machine.env.cluster = Some(ClusterLink {
+                id,
+                mailboxes: mailboxes.clone(),
+            });
+        }
+        Cluster { machines }
+    }
+
+    pub fn run(&mut self) {
+        for machine in self.machines.iter_mut() {
+            machine.set_status(Running);
+        }
+        loop {
+            let mut progress = false;
+            let mut waiting = false;
+            for machine in self.machines.iter_mut() {
+                if machine.status == Blocked {
+                    // retry whatever it was waiting on
+                    machine.set_status(Running);
+                }
+                let ncycles = machine.ncycles();
+                for _ in 0..SLICE {
+                    if !machine.is_running() {
+                        break;
+                    }
+                    machine.cycle();
+                }
+                // a machine waiting on the host may get further without help
+                progress |= machine.ncycles() != ncycles || machine.is_waiting_on_host();
+                waiting |= machine.status == Blocked;
+            }
+            if !waiting && !self.machines.iter().any(Machine::is_running) {
+                return;
+            }
+            if waiting && !progress {
+                // every machine that is still alive is waiting on another one
+                for machine in self.machines.iter_mut() {
+                    if machine.status == Blocked {
+                        machine.set_error(MachineError::Deadlock);
+                    }
+                }
+                return;
+            }
+        }
+    }
+}
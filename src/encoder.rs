@@ -1,7 +1,11 @@
 use std::collections::HashMap;
+use std::ops::Range;
 
 use crate::isa::{Inst, Operation, OP_LIST};
 
+/// Instruction arguments are 24-bit signed values.
+pub const ARG_RANGE: Range<i32> = -0x80_0000..0x80_0000;
+
 #[derive(Clone)]
 pub struct Encoder {
     pub name_to_op: HashMap<&'static str, &'static Operation>,
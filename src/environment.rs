@@ -1,21 +1,114 @@
-use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
 use std::io;
-use std::io::{Read, Write};
+use std::io::{Read, Seek, SeekFrom};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use RetCode::*;
 
+use crate::cluster::ClusterLink;
 use crate::isa::*;
+use crate::machine::MachineStatus;
 use crate::machine::MachineStatus::Stopped;
 use crate::machine::{Machine, MachineError};
 use crate::mem::segs;
+use crate::process;
+use crate::process::{Child, ChildState, Handle};
+use crate::record::RecordLog;
+use crate::sandbox::{Access, Sandbox};
+use crate::threads::ThreadState;
+use crate::trace::TraceOut;
+use crate::vfs::{FileSystem, HostFs};
 
 const FIRST_FD: i32 = 3;
 
 pub(crate) struct Environment {
     heap_ptr: i32,
-    files_open: HashMap<i32, File>,
+    files_open: HashMap<i32, Handle>,
     next_fd: i32,
+    // replace the host's stdin and stdout for spawned programs
+    stdin: Option<Handle>,
+    stdout: Option<Handle>,
+    args: Vec<Vec<u8>>,
+    channels: Vec<Channel>,
+    pub(crate) cluster: Option<ClusterLink>,
+    children: Vec<Child>,
+    next_pid: i32,
+    /// Whether any child got further in the last cycle, so waiting on one isn't a deadlock
+    pub(crate) children_progressed: bool,
+    pub(crate) is_child: bool,
+    pub(crate) sandbox: Sandbox,
+    pub(crate) fs: Rc<RefCell<dyn FileSystem>>,
+    pub(crate) trace: Option<TraceOut>,
+    pub(crate) recording: Option<RecordLog>,
+    started: Instant,
+    pub(crate) rng: Rng,
+}
+
+/// splitmix64, enough for simulations and doesn't need a crate.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// Seeded from the clock, for runs without `--seed`.
+    pub fn from_time() -> Rng {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        Rng::new(since_epoch.as_nanos() as u64)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+struct Channel {
+    capacity: usize,
+    queue: VecDeque<i32>,
+}
+
+impl Environment {
+    /// Runs every child for one cycle.
+    pub(crate) fn step_children(&mut self) {
+        self.children_progressed = false;
+        for child in self.children.iter_mut() {
+            self.children_progressed |= child.step();
+        }
+    }
+
+    fn add_fd(&mut self, handle: Handle) -> i32 {
+        let fd = self.next_fd;
+        self.next_fd += 1;
+        self.files_open.insert(fd, handle);
+        fd
+    }
+
+    /// A copy of what `fd` reads from, or writes to if `writing`, for a child's stdin or stdout.
+    /// None if the child should use the host's, the same way `read_fd` and `write_fd` do.
+    fn share_fd(&self, fd: i32, writing: bool) -> Result<Option<Handle>, RetCode> {
+        let handle = match fd {
+            // fd 1 is stdin for reading and stdout for writing, each maybe redirected already
+            1 if writing => self.stdout.as_ref(),
+            1 => self.stdin.as_ref(),
+            2 if writing => return Ok(Some(Handle::Stderr)),
+            fd => Some(self.files_open.get(&fd).ok_or(InvalidFileDescriptor)?),
+        };
+        match handle {
+            Some(handle) => handle.try_clone().map(Some).map_err(|_| GenericIOError),
+            None => Ok(None),
+        }
+    }
 }
 
 impl Default for Environment {
@@ -24,11 +117,30 @@ impl Default for Environment {
             heap_ptr: segs::HEAP.start(),
             files_open: Default::default(),
             next_fd: FIRST_FD,
+            stdin: None,
+            stdout: None,
+            args: Vec::new(),
+            channels: Vec::new(),
+            cluster: None,
+            children: Vec::new(),
+            next_pid: 1,
+            children_progressed: false,
+            is_child: false,
+            sandbox: Default::default(),
+            fs: Rc::new(RefCell::new(HostFs)),
+            trace: None,
+            recording: None,
+            started: Instant::now(),
+            rng: Rng::from_time(),
         }
     }
 }
 
 pub enum RetCode {
+    PermissionDenied = -9,
+    ChildFaulted = -8,
+    NotInCluster = -7,
+    OutOfResources = -6,
     UTF8Error = -5,
     GenericIOError = -4,
     InvalidFileDescriptor = -3,
@@ -56,14 +168,16 @@ fn open(m: &mut Machine) -> i32 {
             Err(_) => return UTF8Error as i32,
             Ok(s) => s,
         };
-        let file = match OpenOptions::new().write(true).read(true).open(path) {
+        let on_host = m.env.fs.borrow().is_host();
+        let (path, access) = match m.env.sandbox.resolve(&path, on_host) {
+            None => return PermissionDenied as i32,
+            Some(resolved) => resolved,
+        };
+        let file = match m.env.fs.borrow_mut().open(&path, access) {
             Err(_) => return GenericIOError as i32,
             Ok(f) => f,
         };
-        let fd = m.env.next_fd;
-        m.env.next_fd += 1;
-        m.env.files_open.insert(fd, file);
-        fd
+        m.env.add_fd(Handle::File(file, access))
     } else {
         ArgsInvalid as i32
     }
@@ -71,29 +185,52 @@ fn open(m: &mut Machine) -> i32 {
 
 fn write(m: &mut Machine) -> i32 {
     if let (Some(fd), Some(buf_ptr), Some(buf_len)) = (pop(m), pop(m), pop(m)) {
-        let data = match read_machine_memory(m, buf_ptr, buf_len) {
+        write_from_memory(m, fd, buf_ptr, buf_len)
+    } else {
+        ArgsInvalid as i32
+    }
+}
+
+fn read(m: &mut Machine) -> i32 {
+    if let (Some(fd), Some(buf_ptr), Some(buf_len)) = (pop(m), pop(m), pop(m)) {
+        read_into_memory(m, fd, buf_ptr, buf_len)
+    } else {
+        ArgsInvalid as i32
+    }
+}
+
+fn write_from_memory(m: &mut Machine, fd: i32, buf_ptr: i32, buf_len: i32) -> i32 {
+    let data = match read_machine_memory(m, buf_ptr, buf_len) {
+        Err(code) => return code as i32,
+        Ok(data) => data,
+    };
+    match write_fd(m, fd, &data) {
+        Err(code) => code as i32,
+        Ok(nwritten) => nwritten as i32,
+    }
+}
+
+fn read_into_memory(m: &mut Machine, fd: i32, buf_ptr: i32, buf_len: i32) -> i32 {
+    let data = match read_fd(m, fd, buf_len) {
+        Err(code) => return code as i32,
+        Ok(data) => data,
+    };
+    let nread = data.len() as i32;
+    match write_machine_memory(m, buf_ptr, nread, data) {
+        Ok(_) => nread,
+        Err(code) => code as i32,
+    }
+}
+
+/// Like `write`, but the buffer is given as a byte address and length, four bytes per word.
+fn write_packed(m: &mut Machine) -> i32 {
+    if let (Some(fd), Some(byte_ptr), Some(nbytes)) = (pop(m), pop(m), pop(m)) {
+        let data = match read_machine_bytes(m, byte_ptr, nbytes) {
             Err(code) => return code as i32,
             Ok(data) => data,
         };
-        let result = {
-            let mut writer: Box<dyn io::Write> = match fd {
-                1 => Box::new(io::stdout()),
-                2 => Box::new(io::stderr()),
-                fd => match m.env.files_open.get(&fd) {
-                    Some(file) => Box::new(file),
-                    None => return InvalidFileDescriptor as i32,
-                },
-            };
-            match writer.write(&data) {
-                Ok(n) => {
-                    writer.flush().unwrap();
-                    Ok(n)
-                }
-                Err(err) => Err(err),
-            }
-        };
-        match result {
-            Err(_) => GenericIOError as i32,
+        match write_fd(m, fd, &data) {
+            Err(code) => code as i32,
             Ok(nwritten) => nwritten as i32,
         }
     } else {
@@ -101,33 +238,101 @@ fn write(m: &mut Machine) -> i32 {
     }
 }
 
-fn read(m: &mut Machine) -> i32 {
-    if let (Some(fd), Some(buf_ptr), Some(buf_len)) = (pop(m), pop(m), pop(m)) {
-        let mut data = vec![0; buf_len as usize];
-        let result = {
-            let mut reader: Box<dyn io::Read> = match fd {
-                1 => Box::new(io::stdin()),
-                2 => return InvalidFileDescriptor as i32,
-                fd => match m.env.files_open.get(&fd) {
-                    Some(file) => Box::new(file),
-                    None => return InvalidFileDescriptor as i32,
-                },
-            };
-            reader.read(&mut data)
-        };
-        let nread = match result {
-            Err(_) => return GenericIOError as i32,
-            Ok(n) => n as i32,
+/// Like `read`, but the buffer is given as a byte address and length, four bytes per word.
+fn read_packed(m: &mut Machine) -> i32 {
+    if let (Some(fd), Some(byte_ptr), Some(nbytes)) = (pop(m), pop(m), pop(m)) {
+        // before reading, so a bad buffer doesn't consume input or size the read
+        if let Err(code) = byte_bounds_check(byte_ptr, nbytes) {
+            return code as i32;
+        }
+        let data = match read_fd(m, fd, nbytes) {
+            Err(code) => return code as i32,
+            Ok(data) => data,
         };
-        match write_machine_memory(m, buf_ptr, nread, data) {
+        let nread = data.len() as i32;
+        match write_machine_bytes(m, byte_ptr, data) {
             Ok(_) => nread,
-            Err(code) => return code as i32,
+            Err(code) => code as i32,
         }
     } else {
         ArgsInvalid as i32
     }
 }
 
+fn write_fd(m: &mut Machine, fd: i32, data: &[u8]) -> Result<usize, RetCode> {
+    let handle = match fd {
+        1 if m.env.stdout.is_some() => m.env.stdout.as_mut(),
+        1 => return write_host(&mut io::stdout(), data),
+        2 => return write_host(&mut io::stderr(), data),
+        fd => m.env.files_open.get_mut(&fd),
+    };
+    let handle = handle.ok_or(InvalidFileDescriptor)?;
+    let on_host = handle.is_socket();
+    match handle.write(data) {
+        None if on_host => {
+            m.block_on_host();
+            Ok(0)
+        }
+        None => {
+            m.block();
+            Ok(0)
+        }
+        Some(Ok(n)) => Ok(n),
+        // files opened read-only refuse before reaching the filesystem
+        Some(Err(err)) if err.kind() == io::ErrorKind::PermissionDenied => Err(PermissionDenied),
+        Some(Err(err)) if err.kind() == io::ErrorKind::FileTooLarge => Err(OutOfResources),
+        Some(Err(_)) => Err(GenericIOError),
+    }
+}
+
+fn write_host(writer: &mut dyn io::Write, data: &[u8]) -> Result<usize, RetCode> {
+    match writer.write(data) {
+        Ok(n) => {
+            writer.flush().unwrap();
+            Ok(n)
+        }
+        Err(_) => Err(GenericIOError),
+    }
+}
+
+fn read_fd(m: &mut Machine, fd: i32, len: i32) -> Result<Vec<u8>, RetCode> {
+    if len < 0 {
+        return Err(ArgsInvalid);
+    }
+    let mut data = vec![0; len as usize];
+    let handle = match fd {
+        1 if m.env.stdin.is_some() => m.env.stdin.as_mut(),
+        1 => {
+            return match io::Read::read(&mut io::stdin(), &mut data) {
+                Err(_) => Err(GenericIOError),
+                Ok(n) => {
+                    data.truncate(n);
+                    Ok(data)
+                }
+            }
+        }
+        2 => return Err(InvalidFileDescriptor),
+        fd => m.env.files_open.get_mut(&fd),
+    };
+    let handle = handle.ok_or(InvalidFileDescriptor)?;
+    let on_host = handle.is_socket();
+    match handle.read(&mut data) {
+        None if on_host => {
+            m.block_on_host();
+            Ok(Vec::new())
+        }
+        None => {
+            m.block();
+            Ok(Vec::new())
+        }
+        Some(Err(_)) => Err(GenericIOError),
+        Some(Ok(n)) => {
+            data.truncate(n);
+            Ok(data)
+        }
+    }
+}
+
 fn malloc(m: &mut Machine) -> i32 {
     if let Some(size) = pop(m) {
         if m.env.heap_ptr + size >= segs::HEAP.end() {
@@ -141,6 +346,621 @@ fn malloc(m: &mut Machine) -> i32 {
     }
 }
 
+/// Registers the code address of a trap handler, or clears it when given 0.
+fn set_trap_handler(m: &mut Machine) -> i32 {
+    match pop(m) {
+        Some(0) => m.trap_handler = None,
+        Some(addr) if segs::CODE.contains(addr) => m.trap_handler = Some(addr),
+        _ => return ArgsInvalid as i32,
+    }
+    OK as i32
+}
+
+/// Fires a timer interrupt every `interval` cycles, or stops the timer when given 0.
+fn set_timer(m: &mut Machine) -> i32 {
+    match pop(m) {
+        Some(interval) if interval >= 0 => m.set_timer(interval as usize),
+        _ => return ArgsInvalid as i32,
+    }
+    OK as i32
+}
+
+fn set_interrupt_vector(m: &mut Machine) -> i32 {
+    match pop(m) {
+        Some(0) => m.interrupts.vector = None,
+        Some(addr) if segs::CODE.contains(addr) => m.interrupts.vector = Some(addr),
+        _ => return ArgsInvalid as i32,
+    }
+    OK as i32
+}
+
+/// Starts a thread running `entry(arg)` and returns its id.
+fn thread_spawn(m: &mut Machine) -> i32 {
+    if let (Some(entry), Some(arg)) = (pop(m), pop(m)) {
+        if !segs::CODE.contains(entry) {
+            return ArgsInvalid as i32;
+        }
+        match m.spawn_thread(entry, arg) {
+            Some(id) => id as i32,
+            None => OutOfResources as i32,
+        }
+    } else {
+        ArgsInvalid as i32
+    }
+}
+
+/// Formats its arguments and writes them to `fd`, returning the number of bytes written.
+/// The format string is one char per word, the arguments follow it on the stack and
+/// `%s` takes a (ptr, len) pair of one char per word. Conversions are `%d %u %x %c %s`
+/// with an optional width of up to `MAX_PRINTF_WIDTH`, `-` to pad on the right and `0` to pad
+/// numbers with zeros.
+fn printf(m: &mut Machine) -> i32 {
+    if let (Some(fd), Some(fmt_ptr), Some(fmt_len)) = (pop(m), pop(m), pop(m)) {
+        let fmt = match read_machine_memory(m, fmt_ptr, fmt_len) {
+            Err(code) => return code as i32,
+            Ok(fmt) => fmt,
+        };
+        print_formatted(m, fd, &fmt, false)
+    } else {
+        ArgsInvalid as i32
+    }
+}
+
+/// Like `printf`, but the format string and `%s` arguments are byte addresses and lengths.
+fn printf_packed(m: &mut Machine) -> i32 {
+    if let (Some(fd), Some(fmt_ptr), Some(fmt_len)) = (pop(m), pop(m), pop(m)) {
+        let fmt = match read_machine_bytes(m, fmt_ptr, fmt_len) {
+            Err(code) => return code as i32,
+            Ok(fmt) => fmt,
+        };
+        print_formatted(m, fd, &fmt, true)
+    } else {
+        ArgsInvalid as i32
+    }
+}
+
+fn print_formatted(m: &mut Machine, fd: i32, fmt: &[u8], packed: bool) -> i32 {
+    let out = match format_guest(m, fmt, packed) {
+        Err(code) => return code as i32,
+        Ok(out) => out,
+    };
+    match write_fd(m, fd, &out) {
+        Err(code) => code as i32,
+        Ok(nwritten) => nwritten as i32,
+    }
+}
+
+/// Widest field `printf` pads to, so a guest can't make the host allocate without bound.
+const MAX_PRINTF_WIDTH: usize = 4096;
+
+fn format_guest(m: &mut Machine, fmt: &[u8], packed: bool) -> Result<Vec<u8>, RetCode> {
+    let mut out = Vec::new();
+    let mut chars = fmt.iter().cloned().peekable();
+    while let Some(c) = chars.next() {
+        if c != b'%' {
+            out.push(c);
+            continue;
+        }
+        let mut left = false;
+        let mut zeros = false;
+        loop {
+            match chars.peek() {
+                Some(b'-') => left = true,
+                Some(b'0') => zeros = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        let mut width: usize = 0;
+        while let Some(digit @ b'0'..=b'9') = chars.peek().cloned() {
+            width = width
+                .checked_mul(10)
+                .and_then(|width| width.checked_add((digit - b'0') as usize))
+                .filter(|&width| width <= MAX_PRINTF_WIDTH)
+                .ok_or(ArgsInvalid)?;
+            chars.next();
+        }
+        let conv = chars.next().ok_or(ArgsInvalid)?;
+        if conv == b'%' {
+            out.push(b'%');
+            continue;
+        }
+        let arg = pop(m).ok_or(ArgsInvalid)?;
+        let (text, numeric) = match conv {
+            b'd' => (arg.to_string().into_bytes(), true),
+            b'u' => ((arg as u32).to_string().into_bytes(), true),
+            b'x' => (format!("{:x}", arg).into_bytes(), true),
+            b'c' => (vec![arg as u8], false),
+            b's' => {
+                let len = pop(m).ok_or(ArgsInvalid)?;
+                let text = if packed {
+                    read_machine_bytes(m, arg, len)?
+                } else {
+                    read_machine_memory(m, arg, len)?
+                };
+                (text, false)
+            }
+            _ => return Err(ArgsInvalid),
+        };
+        let pad = width.saturating_sub(text.len());
+        if left {
+            out.extend(&text);
+            out.extend(vec![b' '; pad]);
+        } else if zeros && numeric {
+            // zeros go after the sign
+            let (sign, digits) = text.split_at((text[0] == b'-') as usize);
+            out.extend(sign);
+            out.extend(vec![b'0'; pad]);
+            out.extend(digits);
+        } else {
+            out.extend(vec![b' '; pad]);
+            out.extend(&text);
+        }
+    }
+    Ok(out)
+}
+
+/// Stores a 64-bit value at `ptr` as a (lo, hi) pair, like the 64-bit ops keep them on the stack.
+fn store_u64(m: &mut Machine, ptr: i32, val: u64) -> i32 {
+    if let Err(code) = bounds_check(ptr, 2) {
+        return code as i32;
+    }
+    m.store(ptr, val as i32);
+    m.store(ptr + 1, (val >> 32) as i32);
+    OK as i32
+}
+
+/// Stores the milliseconds since the Unix epoch at `ptr` as a (lo, hi) pair.
+fn time_ms(m: &mut Machine) -> i32 {
+    match pop(m) {
+        Some(ptr) => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            store_u64(m, ptr, now.as_millis() as u64)
+        }
+        None => ArgsInvalid as i32,
+    }
+}
+
+/// Stores the nanoseconds since the machine was created at `ptr` as a (lo, hi) pair.
+fn monotonic_ns(m: &mut Machine) -> i32 {
+    match pop(m) {
+        Some(ptr) => {
+            let elapsed = m.env.started.elapsed().as_nanos() as u64;
+            store_u64(m, ptr, elapsed)
+        }
+        None => ArgsInvalid as i32,
+    }
+}
+
+/// Pauses the whole run, other threads and machines included, for `ms` milliseconds.
+fn sleep_ms(m: &mut Machine) -> i32 {
+    match pop(m) {
+        Some(ms) if ms >= 0 => {
+            thread::sleep(Duration::from_millis(ms as u64));
+            OK as i32
+        }
+        _ => ArgsInvalid as i32,
+    }
+}
+
+/// A random word, from a fixed sequence when the run has a `--seed`.
+fn random(m: &mut Machine) -> i32 {
+    m.env.rng.next() as i32
+}
+
+fn thread_yield(m: &mut Machine) -> i32 {
+    m.yield_thread();
+    OK as i32
+}
+
+fn thread_exit(m: &mut Machine) -> i32 {
+    match pop(m) {
+        Some(retval) => m.exit_thread(retval),
+        None => return ArgsInvalid as i32,
+    }
+    OK as i32
+}
+
+/// Waits for a thread to finish and returns its return value.
+fn thread_join(m: &mut Machine) -> i32 {
+    let id = match pop(m) {
+        Some(id) if id >= 0 && (id as usize) < m.threads.list.len() => id as usize,
+        _ => return ArgsInvalid as i32,
+    };
+    if id == m.threads.current {
+        return ArgsInvalid as i32;
+    }
+    match m.threads.list[id].state {
+        ThreadState::Finished(retval) => retval,
+        ThreadState::Runnable => {
+            m.block();
+            OK as i32
+        }
+    }
+}
+
+/// Creates a channel holding up to `capacity` words and returns its id.
+fn chan_new(m: &mut Machine) -> i32 {
+    match pop(m) {
+        Some(capacity) if capacity > 0 => {
+            m.env.channels.push(Channel {
+                capacity: capacity as usize,
+                queue: VecDeque::new(),
+            });
+            m.env.channels.len() as i32 - 1
+        }
+        _ => ArgsInvalid as i32,
+    }
+}
+
+/// Sends a word, waiting while the channel is full.
+fn chan_send(m: &mut Machine) -> i32 {
+    if let (Some(chan), Some(val)) = (pop(m), pop(m)) {
+        let channel = match m.env.channels.get_mut(chan as usize) {
+            Some(channel) => channel,
+            None => return ArgsInvalid as i32,
+        };
+        if channel.queue.len() >= channel.capacity {
+            m.block();
+            return OK as i32;
+        }
+        channel.queue.push_back(val);
+        OK as i32
+    } else {
+        ArgsInvalid as i32
+    }
+}
+
+/// Receives a word, waiting while the channel is empty.
+fn chan_recv(m: &mut Machine) -> i32 {
+    let chan = match pop(m) {
+        Some(chan) => chan,
+        None => return ArgsInvalid as i32,
+    };
+    let channel = match m.env.channels.get_mut(chan as usize) {
+        Some(channel) => channel,
+        None => return ArgsInvalid as i32,
+    };
+    match channel.queue.pop_front() {
+        Some(val) => val,
+        None => {
+            m.block();
+            OK as i32
+        }
+    }
+}
+
+/// The id of this machine within its cluster.
+fn machine_id(m: &mut Machine) -> i32 {
+    match &m.env.cluster {
+        Some(link) => link.id as i32,
+        None => NotInCluster as i32,
+    }
+}
+
+/// Sends a word to another machine's mailbox, waiting while it is full.
+fn msg_send(m: &mut Machine) -> i32 {
+    if let (Some(dest), Some(val)) = (pop(m), pop(m)) {
+        let link = match &m.env.cluster {
+            Some(link) => link.clone(),
+            None => return NotInCluster as i32,
+        };
+        let mut mailboxes = link.mailboxes.borrow_mut();
+        if dest < 0 || dest as usize >= mailboxes.num_machines() {
+            return ArgsInvalid as i32;
+        }
+        if mailboxes.is_full(dest as usize) {
+            m.block();
+            return OK as i32;
+        }
+        mailboxes.send(dest as usize, val);
+        OK as i32
+    } else {
+        ArgsInvalid as i32
+    }
+}
+
+/// Receives a word from this machine's mailbox, waiting while it is empty.
+fn msg_recv(m: &mut Machine) -> i32 {
+    let link = match &m.env.cluster {
+        Some(link) => link.clone(),
+        None => return NotInCluster as i32,
+    };
+    let received = link.mailboxes.borrow_mut().recv(link.id);
+    match received {
+        Some(val) => val,
+        None => {
+            m.block();
+            OK as i32
+        }
+    }
+}
+
+/// The number of arguments the program was started with.
+fn argc(m: &mut Machine) -> i32 {
+    m.env.args.len() as i32
+}
+
+/// Copies argument `i` into a buffer, one char per word, and returns its length.
+fn argv(m: &mut Machine) -> i32 {
+    if let (Some(i), Some(buf_ptr), Some(buf_len)) = (pop(m), pop(m), pop(m)) {
+        let arg = match m.env.args.get(i as usize) {
+            Some(arg) if i >= 0 => arg.clone(),
+            _ => return ArgsInvalid as i32,
+        };
+        if (arg.len() as i32) > buf_len {
+            return ArgsInvalid as i32;
+        }
+        let len = arg.len() as i32;
+        match write_machine_memory(m, buf_ptr, len, arg) {
+            Ok(_) => len,
+            Err(code) => code as i32,
+        }
+    } else {
+        ArgsInvalid as i32
+    }
+}
+
+/// Creates a pipe and stores its read and write fds at `fds[0]` and `fds[1]`.
+fn pipe(m: &mut Machine) -> i32 {
+    let fds_ptr = match pop(m) {
+        Some(ptr) => ptr,
+        None => return ArgsInvalid as i32,
+    };
+    if let Err(code) = bounds_check(fds_ptr, 2) {
+        return code as i32;
+    }
+    let (read_end, write_end) = process::pipe();
+    let read_fd = m.env.add_fd(Handle::Pipe(read_end));
+    let write_fd = m.env.add_fd(Handle::Pipe(write_end));
+    m.store(fds_ptr, read_fd);
+    m.store(fds_ptr + 1, write_fd);
+    if m.has_error() {
+        // the program never learns the fds if the stores fault, so don't keep them open
+        m.env.files_open.remove(&read_fd);
+        m.env.files_open.remove(&write_fd);
+    }
+    OK as i32
+}
+
+fn close(m: &mut Machine) -> i32 {
+    match pop(m) {
+        Some(fd) => match m.env.files_open.remove(&fd) {
+            Some(_) => OK as i32,
+            None => InvalidFileDescriptor as i32,
+        },
+        None => ArgsInvalid as i32,
+    }
+}
+
+/// Starts the program at `path` in a new machine and returns its pid. `argv` points to
+/// `argc` (ptr, len) pairs of strings, `stdin_fd` and `stdout_fd` become the child's fd 1.
+/// Passing 1 shares the parent's own stdin or stdout, and `stdout_fd` may be 2 for stderr.
+fn spawn(m: &mut Machine) -> i32 {
+    let args = (pop(m), pop(m), pop(m), pop(m), pop(m), pop(m));
+    let (path_ptr, path_len, argv_ptr, argc, stdin_fd, stdout_fd) = match args {
+        (Some(a), Some(b), Some(c), Some(d), Some(e), Some(f)) => (a, b, c, d, e, f),
+        _ => return ArgsInvalid as i32,
+    };
+    let path = match read_machine_memory(m, path_ptr, path_len).map(String::from_utf8) {
+        Err(code) => return code as i32,
+        Ok(Err(_)) => return UTF8Error as i32,
+        Ok(Ok(path)) => path,
+    };
+    // programs come from the same filesystem as the files the parent opens
+    let on_host = m.env.fs.borrow().is_host();
+    // running a program only reads it, and the sandbox lets a program read every path it
+    // resolves, so only paths it refuses outright are denied here
+    let fs_path = match m.env.sandbox.resolve(&path, on_host) {
+        None => return PermissionDenied as i32,
+        Some((fs_path, _)) => fs_path,
+    };
+    // argv is argc (ptr, len) pairs
+    let argv_len = match argc.checked_mul(2) {
+        Some(len) if len >= 0 => len,
+        _ => return ArgsInvalid as i32,
+    };
+    if let Err(code) = bounds_check(argv_ptr, argv_len) {
+        return code as i32;
+    }
+    let mut args = vec![path.clone().into_bytes()];
+    for pair in (argv_ptr..argv_ptr + argv_len).step_by(2) {
+        let (arg_ptr, arg_len) = (m.load(pair), m.load(pair + 1));
+        match read_machine_memory(m, arg_ptr, arg_len) {
+            Err(code) => return code as i32,
+            Ok(arg) => args.push(arg),
+        }
+    }
+    let stdin = match m.env.share_fd(stdin_fd, false) {
+        Err(code) => return code as i32,
+        Ok(stdin) => stdin,
+    };
+    let stdout = match m.env.share_fd(stdout_fd, true) {
+        Err(code) => return code as i32,
+        Ok(stdout) => stdout,
+    };
+
+    let mut child = Machine::new();
+    child.max_cycles = m.max_cycles;
+    child.debug_on_error = false;
+    child.trap_overflow = m.trap_overflow;
+    let mut program = Vec::new();
+    let read = m
+        .env
+        .fs
+        .borrow_mut()
+        .open(&fs_path, Access::ReadOnly)
+        .and_then(|mut file| file.read_to_end(&mut program));
+    match read {
+        // the filesystem can refuse a read the sandbox allows, e.g. host file permissions
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            return PermissionDenied as i32
+        }
+        Err(_) => return GenericIOError as i32,
+        Ok(_) => {}
+    }
+    if crate::load_program(&mut child, &path, &program).is_err() {
+        return GenericIOError as i32;
+    }
+    child.env.args = args;
+    child.env.stdin = stdin;
+    child.env.stdout = stdout;
+    child.env.is_child = true;
+    child.env.sandbox = m.env.sandbox.clone();
+    child.env.fs = m.env.fs.clone();
+    child.env.trace = m.env.trace.clone();
+    child.env.recording = m.env.recording.clone();
+    child.env.rng = Rng::new(m.env.rng.next());
+    child.set_status(MachineStatus::Running);
+
+    let pid = m.env.next_pid;
+    m.env.next_pid += 1;
+    m.env.children.push(Child {
+        pid,
+        state: ChildState::Running(Box::new(child)),
+    });
+    pid
+}
+
+/// Waits for a child to finish and returns its exit code.
+fn wait(m: &mut Machine) -> i32 {
+    let pid = match pop(m) {
+        Some(pid) => pid,
+        None => return ArgsInvalid as i32,
+    };
+    let idx = match m.env.children.iter().position(|c| c.pid == pid) {
+        Some(idx) => idx,
+        None => return ArgsInvalid as i32,
+    };
+    match m.env.children[idx].state {
+        ChildState::Exited(code) => {
+            m.env.children.remove(idx);
+            code
+        }
+        ChildState::Running(_) => {
+            m.block();
+            OK as i32
+        }
+    }
+}
+
+/// Moves a file's position to `offset` bytes from its start (whence 0), its current
+/// position (1) or its end (2) and returns the new position.
+fn seek(m: &mut Machine) -> i32 {
+    if let (Some(fd), Some(offset), Some(whence)) = (pop(m), pop(m), pop(m)) {
+        let pos = match whence {
+            0 if offset >= 0 => SeekFrom::Start(offset as u64),
+            1 => SeekFrom::Current(offset as i64),
+            2 => SeekFrom::End(offset as i64),
+            _ => return ArgsInvalid as i32,
+        };
+        let file = match m.env.files_open.get_mut(&fd) {
+            Some(Handle::File(file, _)) => file,
+            _ => return InvalidFileDescriptor as i32,
+        };
+        match file.seek(pos) {
+            Ok(newpos) if newpos <= i32::MAX as u64 => newpos as i32,
+            Ok(_) => ArgsInvalid as i32,
+            Err(err) if err.kind() == io::ErrorKind::FileTooLarge => OutOfResources as i32,
+            Err(_) => GenericIOError as i32,
+        }
+    } else {
+        ArgsInvalid as i32
+    }
+}
+
+/// Reads a "host:port" address string out of machine memory.
+fn read_net_addr(m: &mut Machine, buf_ptr: i32, buf_len: i32) -> Result<String, RetCode> {
+    let data = read_machine_memory(m, buf_ptr, buf_len)?;
+    String::from_utf8(data).map_err(|_| UTF8Error)
+}
+
+/// Listens for TCP connections on an address and returns the listening socket's fd.
+fn socket_listen(m: &mut Machine) -> i32 {
+    if let (Some(buf_ptr), Some(buf_len)) = (pop(m), pop(m)) {
+        let addr = match read_net_addr(m, buf_ptr, buf_len) {
+            Err(code) => return code as i32,
+            Ok(addr) => addr,
+        };
+        match TcpListener::bind(addr).and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        }) {
+            Err(_) => GenericIOError as i32,
+            Ok(listener) => m.env.add_fd(Handle::Listener(listener)),
+        }
+    } else {
+        ArgsInvalid as i32
+    }
+}
+
+/// Waits for a connection on a listening socket and returns the connection's fd.
+fn accept(m: &mut Machine) -> i32 {
+    let fd = match pop(m) {
+        Some(fd) => fd,
+        None => return ArgsInvalid as i32,
+    };
+    let accepted = match m.env.files_open.get(&fd) {
+        Some(Handle::Listener(listener)) => listener.accept(),
+        _ => return InvalidFileDescriptor as i32,
+    };
+    let accepted = accepted.and_then(|(stream, _)| {
+        stream.set_nonblocking(true)?;
+        Ok(stream)
+    });
+    match process::would_block(accepted) {
+        None => {
+            m.block_on_host();
+            0
+        }
+        Some(Err(_)) => GenericIOError as i32,
+        Some(Ok(stream)) => m.env.add_fd(Handle::Stream(stream)),
+    }
+}
+
+/// Opens a TCP connection to an address and returns its fd.
+fn connect(m: &mut Machine) -> i32 {
+    if let (Some(buf_ptr), Some(buf_len)) = (pop(m), pop(m)) {
+        let addr = match read_net_addr(m, buf_ptr, buf_len) {
+            Err(code) => return code as i32,
+            Ok(addr) => addr,
+        };
+        match TcpStream::connect(addr).and_then(|stream| {
+            stream.set_nonblocking(true)?;
+            Ok(stream)
+        }) {
+            Err(_) => GenericIOError as i32,
+            Ok(stream) => m.env.add_fd(Handle::Stream(stream)),
+        }
+    } else {
+        ArgsInvalid as i32
+    }
+}
+
+/// Like `write`, but only for connected sockets.
+fn send(m: &mut Machine) -> i32 {
+    if let (Some(fd), Some(buf_ptr), Some(buf_len)) = (pop(m), pop(m), pop(m)) {
+        match m.env.files_open.get(&fd) {
+            Some(Handle::Stream(_)) => write_from_memory(m, fd, buf_ptr, buf_len),
+            _ => InvalidFileDescriptor as i32,
+        }
+    } else {
+        ArgsInvalid as i32
+    }
+}
+
+/// Like `read`, but only for connected sockets. Returns 0 once the peer has closed the connection.
+fn recv(m: &mut Machine) -> i32 {
+    if let (Some(fd), Some(buf_ptr), Some(buf_len)) = (pop(m), pop(m), pop(m)) {
+        match m.env.files_open.get(&fd) {
+            Some(Handle::Stream(_)) => read_into_memory(m, fd, buf_ptr, buf_len),
+            _ => InvalidFileDescriptor as i32,
+        }
+    } else {
+        ArgsInvalid as i32
+    }
+}
+
 fn read_machine_memory(m: &mut Machine, buf_ptr: i32, buf_len: i32) -> Result<Vec<u8>, RetCode> {
     bounds_check(buf_ptr, buf_len)?;
     Ok((buf_ptr..(buf_ptr + buf_len))
@@ -161,27 +981,540 @@ fn write_machine_memory(
     Ok(())
 }
 
+fn read_machine_bytes(m: &mut Machine, byte_ptr: i32, nbytes: i32) -> Result<Vec<u8>, RetCode> {
+    byte_bounds_check(byte_ptr, nbytes)?;
+    Ok((byte_ptr..(byte_ptr + nbytes))
+        .map(|addr| m.load_byte(addr) as u8)
+        .collect())
+}
+
+fn write_machine_bytes(m: &mut Machine, byte_ptr: i32, data: Vec<u8>) -> Result<(), RetCode> {
+    byte_bounds_check(byte_ptr, data.len() as i32)?;
+    for (addr, val) in (byte_ptr..).zip(data) {
+        m.store_byte(addr, val as i32);
+    }
+    Ok(())
+}
+
+fn byte_bounds_check(byte_ptr: i32, nbytes: i32) -> Result<(), RetCode> {
+    if nbytes < 0 {
+        return Err(ArgsInvalid);
+    }
+    let nwords = (nbytes as i64 + 3) / 4;
+    // round down, so negative byte pointers land below the address space too
+    bounds_check(byte_ptr.div_euclid(4), nwords as i32)
+}
+
 fn bounds_check(buf_ptr: i32, buf_len: i32) -> Result<(), RetCode> {
-    if buf_ptr < segs::ADDR_SPACE.start || (buf_ptr + buf_len) >= segs::ADDR_SPACE.end {
+    // in i64, so a length near i32::MAX can't wrap the end back into the address space
+    let end = buf_ptr as i64 + buf_len as i64;
+    if buf_ptr < segs::ADDR_SPACE.start || end >= segs::ADDR_SPACE.end as i64 {
         return Err(AddressOutOfBounds);
     }
     Ok(())
 }
 
 macro_rules! def_env_call_list {
-    ( $($name:ident)+ ) => {
-        pub const CALL_LIST: &[(fn(&mut Machine) -> i32, &'static str)] = &[
+    ( $($name:ident/$nargs:literal)+ ) => {
+        /// (function, name, number of arguments it pops, not counting printf's format arguments)
+        pub const CALL_LIST: &[(fn(&mut Machine) -> i32, &'static str, usize)] = &[
             $(
-                ($name, stringify!($name)),
+                ($name, stringify!($name), $nargs),
             )+
         ];
     }
 }
 
 def_env_call_list![
-    exit
-    open
-    write
-    read
-    malloc
+    exit/1
+    open/2
+    write/3
+    read/3
+    malloc/1
+    write_packed/3
+    read_packed/3
+    set_trap_handler/1
+    set_timer/1
+    set_interrupt_vector/1
+    thread_spawn/2
+    thread_yield/0
+    thread_exit/1
+    thread_join/1
+    chan_new/1
+    chan_send/2
+    chan_recv/1
+    machine_id/0
+    msg_send/2
+    msg_recv/0
+    argc/0
+    argv/3
+    pipe/1
+    close/1
+    spawn/6
+    wait/1
+    socket_listen/2
+    accept/1
+    connect/2
+    send/3
+    recv/3
+    seek/3
+    time_ms/1
+    monotonic_ns/1
+    sleep_ms/1
+    random/0
+    printf/3
+    printf_packed/3
 ];
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::sandbox::EcallFamily;
+    use crate::vfs::MemFs;
+
+    /// Makes an ecall with its arguments in the order the guest would pass them.
+    fn call(m: &mut Machine, name: &str, args: &[i32]) -> i32 {
+        for &arg in args.iter().rev() {
+            push(m, arg);
+        }
+        let callcode = CALL_LIST.iter().position(|(_, n, _)| *n == name).unwrap();
+        ecall(m, callcode as i32);
+        pop(m).unwrap()
+    }
+
+    /// Like `call`, but retries while the ecall waits on the host, the way a thread would.
+    fn call_until_ready(m: &mut Machine, name: &str, args: &[i32]) -> i32 {
+        for _ in 0..1000 {
+            let retval = call(m, name, args);
+            if !m.is_blocking() {
+                return retval;
+            }
+            m.threads.request = None;
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("{} never stopped waiting", name);
+    }
+
+    /// Stores a string one character per word in the heap, returning its address and length.
+    fn put_str(m: &mut Machine, s: &str) -> (i32, i32) {
+        let ptr = segs::HEAP.start();
+        for (addr, byte) in (ptr..).zip(s.bytes()) {
+            m.store(addr, byte as i32);
+        }
+        (ptr, s.len() as i32)
+    }
+
+    fn get_str(m: &mut Machine, ptr: i32, len: i32) -> String {
+        (ptr..ptr + len)
+            .map(|addr| m.load(addr) as u8 as char)
+            .collect()
+    }
+
+    fn net_machine() -> Machine {
+        let mut m = Machine::new();
+        m.env.sandbox.disabled.remove(&EcallFamily::Net);
+        m
+    }
+
+    #[test]
+    fn sockets_are_off_by_default() {
+        let mut m = Machine::new();
+        let (ptr, len) = put_str(&mut m, "127.0.0.1:0");
+        assert_eq!(
+            call(&mut m, "socket_listen", &[ptr, len]),
+            PermissionDenied as i32
+        );
+        assert_eq!(
+            call(&mut m, "connect", &[ptr, len]),
+            PermissionDenied as i32
+        );
+    }
+
+    #[test]
+    fn connect_send_and_recv() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut m = net_machine();
+        let (ptr, len) = put_str(&mut m, &listener.local_addr().unwrap().to_string());
+        let fd = call(&mut m, "connect", &[ptr, len]);
+        assert!(fd >= FIRST_FD, "connect returned {}", fd);
+        let (mut peer, _) = listener.accept().unwrap();
+
+        let (ptr, len) = put_str(&mut m, "ping");
+        assert_eq!(call(&mut m, "send", &[fd, ptr, len]), 4);
+        let mut buf = [0; 4];
+        peer.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+
+        peer.write_all(b"pong").unwrap();
+        assert_eq!(call_until_ready(&mut m, "recv", &[fd, ptr, 4]), 4);
+        assert_eq!(get_str(&mut m, ptr, 4), "pong");
+
+        std::mem::drop(peer);
+        assert_eq!(call_until_ready(&mut m, "recv", &[fd, ptr, 4]), 0);
+    }
+
+    #[test]
+    fn listen_and_accept() {
+        let mut m = net_machine();
+        let (ptr, len) = put_str(&mut m, "127.0.0.1:0");
+        let listen_fd = call(&mut m, "socket_listen", &[ptr, len]);
+        let addr = match m.env.files_open.get(&listen_fd) {
+            Some(Handle::Listener(listener)) => listener.local_addr().unwrap(),
+            _ => panic!("socket_listen returned {}", listen_fd),
+        };
+        let mut peer = TcpStream::connect(addr).unwrap();
+        let fd = call_until_ready(&mut m, "accept", &[listen_fd]);
+        assert!(fd > listen_fd, "accept returned {}", fd);
+
+        peer.write_all(b"hi").unwrap();
+        assert_eq!(call_until_ready(&mut m, "recv", &[fd, ptr, 2]), 2);
+        assert_eq!(get_str(&mut m, ptr, 2), "hi");
+    }
+
+    #[test]
+    fn idle_sockets_wait_on_the_host() {
+        let mut m = net_machine();
+        let (ptr, len) = put_str(&mut m, "127.0.0.1:0");
+        let listen_fd = call(&mut m, "socket_listen", &[ptr, len]);
+        assert_eq!(call(&mut m, "accept", &[listen_fd]), OK as i32);
+        assert!(m.is_blocking() && m.is_waiting_on_host());
+        m.threads.request = None;
+
+        let addr = match m.env.files_open.get(&listen_fd) {
+            Some(Handle::Listener(listener)) => listener.local_addr().unwrap(),
+            _ => panic!("socket_listen returned {}", listen_fd),
+        };
+        let _peer = TcpStream::connect(addr).unwrap();
+        let fd = call_until_ready(&mut m, "accept", &[listen_fd]);
+        call(&mut m, "recv", &[fd, ptr, 4]);
+        assert!(m.is_blocking() && m.is_waiting_on_host());
+    }
+
+    #[test]
+    fn send_and_recv_only_take_sockets() {
+        let mut m = net_machine();
+        let (ptr, len) = put_str(&mut m, "x");
+        assert_eq!(
+            call(&mut m, "send", &[2, ptr, len]),
+            InvalidFileDescriptor as i32
+        );
+        assert_eq!(
+            call(&mut m, "recv", &[1, ptr, len]),
+            InvalidFileDescriptor as i32
+        );
+    }
+
+    #[test]
+    fn bounds_checks_handle_lengths_near_i32_max() {
+        let heap = segs::HEAP.start();
+        assert!(bounds_check(heap, 4).is_ok());
+        assert!(matches!(
+            bounds_check(heap, i32::MAX),
+            Err(AddressOutOfBounds)
+        ));
+        assert!(byte_bounds_check(heap * 4, 8).is_ok());
+        assert!(matches!(
+            byte_bounds_check(heap * 4, i32::MAX),
+            Err(AddressOutOfBounds)
+        ));
+        assert!(matches!(
+            byte_bounds_check(i32::MAX, 4),
+            Err(AddressOutOfBounds)
+        ));
+        assert!(matches!(byte_bounds_check(heap * 4, -1), Err(ArgsInvalid)));
+
+        let mut m = Machine::new();
+        assert_eq!(
+            call(&mut m, "write_packed", &[2, heap * 4, i32::MAX]),
+            AddressOutOfBounds as i32
+        );
+        assert_eq!(
+            call(&mut m, "read_packed", &[1, heap * 4, i32::MAX]),
+            AddressOutOfBounds as i32
+        );
+    }
+
+    #[test]
+    fn read_only_files_refuse_writes() {
+        let mut fs = MemFs::default();
+        fs.insert("in.txt".into(), b"abc".to_vec());
+        fs.insert("out.txt".into(), Vec::new());
+        let mut m = Machine::new();
+        m.env.fs = Rc::new(RefCell::new(fs));
+        m.env.sandbox.read_only = vec!["/in.txt".into()];
+        m.env.sandbox.read_write = vec!["/out.txt".into()];
+
+        let (ptr, len) = put_str(&mut m, "in.txt");
+        let fd = call(&mut m, "open", &[ptr, len]);
+        assert_eq!(
+            call(&mut m, "write", &[fd, ptr, 1]),
+            PermissionDenied as i32
+        );
+        assert_eq!(call(&mut m, "read", &[fd, ptr, 3]), 3);
+        assert_eq!(get_str(&mut m, ptr, 3), "abc");
+
+        let (ptr, len) = put_str(&mut m, "out.txt");
+        let fd = call(&mut m, "open", &[ptr, len]);
+        assert_eq!(call(&mut m, "write", &[fd, ptr, len]), len);
+    }
+
+    /// Copies one read of its stdin to its stdout.
+    const CAT: &str = "
+main:
+    push
+    jal cat
+    ecall .cc.exit
+
+cat:
+    .local buf 16
+    .addr_of buf
+    .local n 1
+    .start_frame
+    .call env.read p:.fd.stdin lf:buf.addr p:.sizeof.buf ret:n
+    .call env.write p:.fd.stdout lf:buf.addr lf:n
+    push 0
+    storef retval
+    .end_frame
+    ret
+";
+
+    /// A machine with `cat.asm` in its filesystem.
+    fn parent_of_cat() -> Machine {
+        let mut fs = MemFs::default();
+        fs.insert("cat.asm".into(), CAT.as_bytes().to_vec());
+        let mut m = Machine::new();
+        m.env.fs = Rc::new(RefCell::new(fs));
+        m
+    }
+
+    fn spawn_cat(m: &mut Machine, stdin_fd: i32, stdout_fd: i32) -> i32 {
+        let (ptr, len) = put_str(m, "cat.asm");
+        call(m, "spawn", &[ptr, len, 0, 0, stdin_fd, stdout_fd])
+    }
+
+    /// Steps the children until `pid` exits and returns its exit code.
+    fn run_child(m: &mut Machine, pid: i32) -> i32 {
+        for _ in 0..10_000 {
+            m.env.step_children();
+            let child = m.env.children.iter().find(|c| c.pid == pid).unwrap();
+            if let ChildState::Exited(code) = child.state {
+                return code;
+            }
+        }
+        panic!("child {} never exited", pid);
+    }
+
+    fn read_pipe(mut end: Handle) -> String {
+        let mut buf = [0; 64];
+        let n = end.read(&mut buf).unwrap().unwrap();
+        String::from_utf8(buf[..n].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn children_share_a_redirected_stdin_and_stdout() {
+        let mut m = parent_of_cat();
+        let (in_read, in_write) = process::pipe();
+        let (out_read, out_write) = process::pipe();
+        Handle::Pipe(in_write).write(b"hi").unwrap().unwrap();
+        m.env.stdin = Some(Handle::Pipe(in_read));
+        m.env.stdout = Some(Handle::Pipe(out_write));
+
+        let pid = spawn_cat(&mut m, 1, 1);
+        assert!(pid > 0, "spawn returned {}", pid);
+        assert_eq!(run_child(&mut m, pid), 0);
+        assert_eq!(read_pipe(Handle::Pipe(out_read)), "hi");
+    }
+
+    #[test]
+    fn children_take_pipe_fds() {
+        let mut m = parent_of_cat();
+        let fds = segs::HEAP.start() + 100;
+        assert_eq!(call(&mut m, "pipe", &[fds]), OK as i32);
+        let (read_fd, write_fd) = (m.load(fds), m.load(fds + 1));
+        let (out_read, out_write) = process::pipe();
+        let out_fd = m.env.add_fd(Handle::Pipe(out_write));
+
+        let (ptr, len) = put_str(&mut m, "piped");
+        assert_eq!(call(&mut m, "write", &[write_fd, ptr, len]), len);
+        assert_eq!(call(&mut m, "close", &[write_fd]), OK as i32);
+        let pid = spawn_cat(&mut m, read_fd, out_fd);
+        assert_eq!(run_child(&mut m, pid), 0);
+        assert_eq!(read_pipe(Handle::Pipe(out_read)), "piped");
+    }
+
+    #[test]
+    fn children_spawn_only_from_readable_paths() {
+        let mut m = parent_of_cat();
+        m.env.sandbox.read_only = vec!["/other.asm".into()];
+        assert_eq!(spawn_cat(&mut m, 1, 1), PermissionDenied as i32);
+        assert!(m.env.children.is_empty());
+
+        // read-only is enough to run a program
+        m.env.sandbox.read_only = vec!["/cat.asm".into()];
+        assert!(spawn_cat(&mut m, 1, 1) > 0);
+    }
+
+    #[test]
+    fn children_get_their_args() {
+        let mut m = parent_of_cat();
+        let (path_ptr, path_len) = put_str(&mut m, "cat.asm");
+        let argv = segs::HEAP.start() + 100;
+        let strs = segs::HEAP.start() + 200;
+        for (i, arg) in ["-n", "x"].iter().enumerate() {
+            let ptr = strs + 10 * i as i32;
+            for (j, c) in arg.bytes().enumerate() {
+                m.store(ptr + j as i32, c as i32);
+            }
+            m.store(argv + 2 * i as i32, ptr);
+            m.store(argv + 2 * i as i32 + 1, arg.len() as i32);
+        }
+        let pid = call(&mut m, "spawn", &[path_ptr, path_len, argv, 2, 1, 1]);
+        match &m.env.children.iter().find(|c| c.pid == pid).unwrap().state {
+            ChildState::Running(child) => assert_eq!(
+                child.env.args,
+                vec![b"cat.asm".to_vec(), b"-n".to_vec(), b"x".to_vec()]
+            ),
+            _ => panic!("child {} isn't running", pid),
+        }
+
+        for argc in &[-1, i32::MAX] {
+            assert_eq!(
+                call(&mut m, "spawn", &[path_ptr, path_len, argv, *argc, 1, 1]),
+                ArgsInvalid as i32
+            );
+        }
+    }
+
+    #[test]
+    fn faulting_pipes_close_their_fds() {
+        let mut m = Machine::new();
+        call(&mut m, "pipe", &[segs::CODE.start()]);
+        assert!(m.has_error());
+        assert!(m.env.files_open.is_empty());
+    }
+
+    #[test]
+    fn children_load_binaries_word_by_word() {
+        let binary = crate::assembler::assemble_from_source(CAT.as_bytes())
+            .unwrap()
+            .binary;
+        let bytes: Vec<u8> = binary.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let mut fs = MemFs::default();
+        fs.insert("cat.bin".into(), bytes.clone());
+        fs.insert("torn.bin".into(), bytes[..bytes.len() - 1].to_vec());
+        let mut m = Machine::new();
+        m.env.fs = Rc::new(RefCell::new(fs));
+        let (in_read, in_write) = process::pipe();
+        let (out_read, out_write) = process::pipe();
+        Handle::Pipe(in_write).write(b"bin").unwrap().unwrap();
+        m.env.stdin = Some(Handle::Pipe(in_read));
+        m.env.stdout = Some(Handle::Pipe(out_write));
+
+        let (ptr, len) = put_str(&mut m, "cat.bin");
+        let pid = call(&mut m, "spawn", &[ptr, len, 0, 0, 1, 1]);
+        assert_eq!(run_child(&mut m, pid), 0);
+        assert_eq!(read_pipe(Handle::Pipe(out_read)), "bin");
+
+        let (ptr, len) = put_str(&mut m, "torn.bin");
+        assert_eq!(
+            call(&mut m, "spawn", &[ptr, len, 0, 0, 1, 1]),
+            GenericIOError as i32
+        );
+    }
+
+    #[test]
+    fn memfs_files_are_capped() {
+        let mut fs = MemFs::default();
+        fs.insert("big".into(), Vec::new());
+        let mut m = Machine::new();
+        m.env.fs = Rc::new(RefCell::new(fs));
+        let (ptr, len) = put_str(&mut m, "big");
+        let fd = call(&mut m, "open", &[ptr, len]);
+        let cap = crate::vfs::MAX_FILE_SIZE as i32;
+        assert_eq!(
+            call(&mut m, "seek", &[fd, cap + 1, 0]),
+            OutOfResources as i32
+        );
+        assert_eq!(call(&mut m, "seek", &[fd, cap, 0]), cap);
+        assert_eq!(
+            call(&mut m, "write", &[fd, ptr, len]),
+            OutOfResources as i32
+        );
+    }
+
+    #[test]
+    fn shared_fds_follow_read_and_write() {
+        let m = Machine::new();
+        assert!(matches!(m.env.share_fd(1, false), Ok(None)));
+        assert!(matches!(m.env.share_fd(1, true), Ok(None)));
+        assert!(matches!(m.env.share_fd(2, true), Ok(Some(Handle::Stderr))));
+        assert!(matches!(
+            m.env.share_fd(2, false),
+            Err(InvalidFileDescriptor)
+        ));
+        assert!(matches!(
+            m.env.share_fd(FIRST_FD, false),
+            Err(InvalidFileDescriptor)
+        ));
+    }
+
+    fn printf(m: &mut Machine, fmt: &str, args: &[i32]) -> Result<String, RetCode> {
+        for &arg in args.iter().rev() {
+            push(m, arg);
+        }
+        let out = format_guest(m, fmt.as_bytes(), false)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn printf_formats_numbers() {
+        let mut m = Machine::new();
+        let out = printf(&mut m, "%d %u %x %c%%", &[-42, -1, 255, 'A' as i32]);
+        assert_eq!(out.ok().unwrap(), "-42 4294967295 ff A%");
+    }
+
+    #[test]
+    fn printf_pads_to_width() {
+        let mut m = Machine::new();
+        let out = printf(&mut m, "[%5d][%-5d][%05d][%05x]", &[42, 42, -42, 255]);
+        assert_eq!(out.ok().unwrap(), "[   42][42   ][-0042][000ff]");
+        let out = printf(&mut m, "[%1d][%03c]", &[-123, 'z' as i32]);
+        assert_eq!(out.ok().unwrap(), "[-123][  z]");
+    }
+
+    #[test]
+    fn printf_reads_strings() {
+        let mut m = Machine::new();
+        let (ptr, len) = put_str(&mut m, "hello");
+        let out = printf(&mut m, "<%s> <%-7s>", &[ptr, len, ptr, 2]);
+        assert_eq!(out.ok().unwrap(), "<hello> <he     >");
+
+        m.store(ptr, i32::from_be_bytes(*b"pack"));
+        push(&mut m, 3);
+        push(&mut m, ptr * 4);
+        let out = format_guest(&mut m, b"%s!", true).ok().unwrap();
+        assert_eq!(out, b"pac!");
+    }
+
+    #[test]
+    fn printf_rejects_bad_formats() {
+        let mut m = Machine::new();
+        for fmt in ["%", "%5", "%q"].iter() {
+            assert!(printf(&mut m, fmt, &[]).is_err(), "{:?} formatted", fmt);
+        }
+        assert!(printf(&mut m, "%s", &[-5, 1]).is_err());
+    }
+
+    #[test]
+    fn printf_caps_widths() {
+        let mut m = Machine::new();
+        let out = printf(&mut m, "%4096d", &[7]).ok().unwrap();
+        assert_eq!(out.len(), MAX_PRINTF_WIDTH);
+        for fmt in ["%4097d", "%9999999999999d", "%99999999999999999999d"].iter() {
+            assert!(printf(&mut m, fmt, &[7]).is_err(), "{:?} formatted", fmt);
+        }
+    }
+}
@@ -1,9 +1,13 @@
 use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
+use std::ops::Neg;
 
 use crate::environment;
 use crate::machine::MachineError;
 use crate::mem::addrs;
+use crate::record;
+use crate::threads;
+use crate::trace;
 
 use super::Machine;
 
@@ -22,6 +26,17 @@ pub fn pop(m: &mut Machine) -> Option<i32> {
     top
 }
 
+/// Pushes `imm << 16`, to be followed by `orlo` to build a full 32-bit constant.
+pub fn pushhi(m: &mut Machine, imm: i32) {
+    push(m, imm << 16);
+}
+
+pub fn orlo(m: &mut Machine, imm: i32) {
+    if let Some(top) = pop(m) {
+        push(m, top | (imm & 0xffff));
+    }
+}
+
 pub fn loadi(m: &mut Machine, addr: i32) {
     let val = m.load(addr);
     push(m, val);
@@ -38,6 +53,52 @@ pub fn addsp(m: &mut Machine, delta: i32) {
     m.setsp(sp + delta);
 }
 
+pub fn dup(m: &mut Machine, _: i32) {
+    if let Some(top) = pop(m) {
+        push(m, top);
+        push(m, top);
+    }
+}
+
+pub fn swap(m: &mut Machine, _: i32) {
+    if let (Some(top), Some(sec)) = (pop(m), pop(m)) {
+        push(m, top);
+        push(m, sec);
+    }
+}
+
+pub fn over(m: &mut Machine, _: i32) {
+    if let (Some(top), Some(sec)) = (pop(m), pop(m)) {
+        push(m, sec);
+        push(m, top);
+        push(m, sec);
+    }
+}
+
+pub fn rot(m: &mut Machine, _: i32) {
+    if let (Some(top), Some(sec), Some(third)) = (pop(m), pop(m), pop(m)) {
+        push(m, sec);
+        push(m, top);
+        push(m, third);
+    }
+}
+
+pub fn drop(m: &mut Machine, _: i32) {
+    pop(m);
+}
+
+pub fn pick(m: &mut Machine, depth: i32) {
+    let sp = m.getsp();
+    let addr = sp - 1 - depth;
+    if addr < m.stack_range().start {
+        m.set_error(MachineError::StackUnderflow { sp, depth });
+        return;
+    }
+    if let Some(val) = m.stack_load(addr) {
+        push(m, val);
+    }
+}
+
 pub fn load(m: &mut Machine, offset: i32) {
     if let Some(addr) = pop(m) {
         let val = m.load(addr + offset);
@@ -51,6 +112,111 @@ pub fn store(m: &mut Machine, offset: i32) {
     }
 }
 
+macro_rules! byte_load_store_funcs {
+    ( $($load_name:ident $store_name:ident ($load_method:ident $store_method:ident));+; ) => {
+        $(
+            pub fn $load_name(m: &mut Machine, offset: i32) {
+                if let Some(byte_addr) = pop(m) {
+                    let val = m.$load_method(byte_addr + offset);
+                    push(m, val);
+                }
+            }
+
+            pub fn $store_name(m: &mut Machine, offset: i32) {
+                if let (Some(byte_addr), Some(val)) = (pop(m), pop(m)) {
+                    m.$store_method(byte_addr + offset, val);
+                }
+            }
+        )+
+    }
+}
+
+byte_load_store_funcs! {
+    loadb storeb ( load_byte store_byte );
+    loadh storeh ( load_half store_half );
+}
+
+// Bulk memory ops go through `Machine::load` and `Machine::store` for every word, so they
+// get the same segment checks, and cost one extra cycle per word.
+
+/// Whether `len` words from `addr` can be walked without overflowing. A negative length or
+/// one that runs past i32::MAX faults like an access outside the address space.
+fn range_ok(m: &mut Machine, addr: i32, len: i32, store: bool) -> bool {
+    if len >= 0 && addr.checked_add(len).is_some() {
+        return true;
+    }
+    m.set_error(if store {
+        MachineError::StoreAddressOutOfBounds { addr }
+    } else {
+        MachineError::LoadAddressOutOfBounds { addr }
+    });
+    false
+}
+
+fn copy_words(m: &mut Machine, dst: i32, src: i32, len: i32, backwards: bool) {
+    if !range_ok(m, src, len, false) || !range_ok(m, dst, len, true) {
+        return;
+    }
+    m.add_cycles(len);
+    for i in 0..len {
+        let i = if backwards { len - 1 - i } else { i };
+        let val = m.load(src + i);
+        m.store(dst + i, val);
+        if m.has_error() {
+            return;
+        }
+    }
+}
+
+pub fn memcpy(m: &mut Machine, _: i32) {
+    if let (Some(dst), Some(src), Some(len)) = (pop(m), pop(m), pop(m)) {
+        copy_words(m, dst, src, len, false);
+    }
+}
+
+pub fn memmove(m: &mut Machine, _: i32) {
+    if let (Some(dst), Some(src), Some(len)) = (pop(m), pop(m), pop(m)) {
+        copy_words(m, dst, src, len, dst > src);
+    }
+}
+
+pub fn memset(m: &mut Machine, _: i32) {
+    if let (Some(dst), Some(val), Some(len)) = (pop(m), pop(m), pop(m)) {
+        if !range_ok(m, dst, len, true) {
+            return;
+        }
+        m.add_cycles(len);
+        for addr in dst..dst + len {
+            m.store(addr, val);
+            if m.has_error() {
+                return;
+            }
+        }
+    }
+}
+
+/// Pushes -1, 0 or 1 as the first differing word of `a` is less than, equal to
+/// or greater than the one in `b`.
+pub fn memcmp(m: &mut Machine, _: i32) {
+    if let (Some(a), Some(b), Some(len)) = (pop(m), pop(m), pop(m)) {
+        if !range_ok(m, a, len, false) || !range_ok(m, b, len, false) {
+            return;
+        }
+        m.add_cycles(len);
+        for i in 0..len {
+            let (x, y) = (m.load(a + i), m.load(b + i));
+            if m.has_error() {
+                return;
+            }
+            if x != y {
+                push(m, if x < y { -1 } else { 1 });
+                return;
+            }
+        }
+        push(m, 0);
+    }
+}
+
 fn getfp(m: &mut Machine) -> i32 {
     m.stack_load(addrs::FP).expect("frame pointer invalid")
 }
@@ -84,8 +250,30 @@ pub fn ecall(m: &mut Machine, callcode: i32) {
         m.set_error(MachineError::NoSuchEnvCall(callcode));
         return;
     }
-    let (env_call_func, _) = environment::CALL_LIST[callcode as usize];
-    let retval = env_call_func(m);
+    let (env_call_func, name, nargs) = environment::CALL_LIST[callcode as usize];
+    let traced_args = m.env.trace.as_ref().map(|_| trace::peek_args(m, nargs));
+    let was_waiting_on_host = m.is_waiting_on_host();
+    let retval = if !m.env.sandbox.allows_call(name) {
+        for _ in 0..nargs {
+            pop(m);
+        }
+        environment::RetCode::PermissionDenied as i32
+    } else if !record::is_recorded(name) {
+        env_call_func(m)
+    } else if let Some(retval) = record::replay(m, name, nargs) {
+        retval
+    } else {
+        let journal_start = m.journal_len();
+        let retval = env_call_func(m);
+        record::record(m, name, retval, journal_start);
+        retval
+    };
+    if let Some(args) = traced_args {
+        // a host wait is polled every cycle, only its first attempt is shown
+        if !(was_waiting_on_host && m.is_blocking()) {
+            trace::log_call(m, name, &args, retval);
+        }
+    }
     push(m, retval);
 }
 
@@ -104,19 +292,74 @@ pub fn jal(m: &mut Machine, offset: i32) {
     m.setpc(pc + offset);
 }
 
+pub fn jumpr(m: &mut Machine, offset: i32) {
+    if let Some(addr) = pop(m) {
+        // a wrapped target is never in the code segment, so jump_to faults on it
+        m.jump_to(addr.wrapping_add(offset));
+    }
+}
+
+pub fn jalr(m: &mut Machine, offset: i32) {
+    if let Some(addr) = pop(m) {
+        let pc = m.getpc();
+        push(m, pc);
+        m.jump_to(addr.wrapping_add(offset));
+    }
+}
+
 pub fn ret(m: &mut Machine, _: i32) {
     if let Some(addr) = pop(m) {
+        if addr == threads::THREAD_RETURN && m.is_spawned_thread() {
+            if let Some(retval) = pop(m) {
+                m.exit_thread(retval);
+            }
+            return;
+        }
         m.setpc(addr);
     }
 }
 
+/// Returns from a trap handler to the saved PC, retrying the faulting instruction.
+/// Handlers that want to skip it add 1 to the saved PC first.
+pub fn rettrap(m: &mut Machine, _: i32) {
+    if let (Some(pc), Some(_code)) = (pop(m), pop(m)) {
+        m.return_from_trap(pc);
+    }
+}
+
+pub fn ei(m: &mut Machine, _: i32) {
+    m.interrupts.enabled = true;
+}
+
+pub fn di(m: &mut Machine, _: i32) {
+    m.interrupts.enabled = false;
+}
+
+pub fn iret(m: &mut Machine, _: i32) {
+    if let (Some(pc), Some(status)) = (pop(m), pop(m)) {
+        m.return_from_interrupt(pc, status);
+    }
+}
+
 pub fn invald(m: &mut Machine, _: i32) {
     m.set_error(MachineError::InvalidInstruction);
 }
 
+/// Narrows a result computed in 64 bits back to a word. Overflow wraps, unless
+/// `trap` or the machine-wide `trap_overflow` option is set.
+fn narrow(m: &mut Machine, res: i64, trap: bool) -> Option<i32> {
+    let wrapped = res as i32;
+    if (trap || m.trap_overflow) && wrapped as i64 != res {
+        let pc = m.getpc();
+        m.set_error(MachineError::ArithmeticOverflow { pc });
+        return None;
+    }
+    Some(wrapped)
+}
+
 macro_rules! with_overflow {
-    ($top:ident $op:tt $arg:ident) => {
-        (($top as i64) $op ($arg as i64)) as i32
+    ($m:ident, $top:ident $op:tt $arg:ident) => {
+        narrow($m, ($top as i64) $op ($arg as i64), false)
     };
 }
 
@@ -126,9 +369,11 @@ macro_rules! binary_op_funcs {
             #[allow(unused)]
             pub fn $name(m: &mut Machine, imm: i32) {
                 if let (Some(top), Some(sec)) = (pop(m), pop(m)) {
-                    let mut res = with_overflow!(top $operator sec);
-                    res = with_overflow!(res $operator imm);
-                    push(m, res);
+                    if let Some(res) = with_overflow!(m, top $operator sec) {
+                        if let Some(res) = with_overflow!(m, res $operator imm) {
+                            push(m, res);
+                        }
+                    }
                 }
             }
         )+
@@ -139,8 +384,6 @@ binary_op_funcs! {
     add ( + );
     sub ( - );
     mul ( * );
-    div ( / );
-    rem ( % );
     and ( & );
     or  ( | );
     xor ( ^ );
@@ -152,7 +395,9 @@ macro_rules! binary_op_imm_funcs {
             #[allow(unused)]
             pub fn $name(m: &mut Machine, imm: i32) {
                 if let Some(top) = pop(m) {
-                    push(m, with_overflow!(top $operator imm));
+                    if let Some(res) = with_overflow!(m, top $operator imm) {
+                        push(m, res);
+                    }
                 }
             }
         )+
@@ -163,8 +408,6 @@ binary_op_imm_funcs! {
     addi ( + );
     subi ( - );
     muli ( * );
-    divi ( / );
-    remi ( % );
     andi ( & );
     ori  ( | );
     xori ( ^ );
@@ -194,14 +437,225 @@ branch_cmp_funcs! {
     bge ( >= );
 }
 
+macro_rules! checked_binary_op_funcs {
+    ( $($name:ident ($operator:tt));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, _: i32) {
+                if let (Some(top), Some(sec)) = (pop(m), pop(m)) {
+                    if let Some(res) = narrow(m, (top as i64) $operator (sec as i64), true) {
+                        push(m, res);
+                    }
+                }
+            }
+        )+
+    }
+}
+
+checked_binary_op_funcs! {
+    addo ( + );
+    subo ( - );
+    mulo ( * );
+}
+
+macro_rules! checked_binary_op_imm_funcs {
+    ( $($name:ident ($operator:tt));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, imm: i32) {
+                if let Some(top) = pop(m) {
+                    if let Some(res) = narrow(m, (top as i64) $operator (imm as i64), true) {
+                        push(m, res);
+                    }
+                }
+            }
+        )+
+    }
+}
+
+checked_binary_op_imm_funcs! {
+    addoi ( + );
+    suboi ( - );
+    muloi ( * );
+}
+
+/// ( sec top carry -- sum carry ): top + sec + carry, pushing the carry out on top.
+pub fn addc(m: &mut Machine, _: i32) {
+    if let (Some(cin), Some(top), Some(sec)) = (pop(m), pop(m), pop(m)) {
+        let sum = (top as u32 as u64) + (sec as u32 as u64) + (cin != 0) as u64;
+        push(m, sum as i32);
+        push(m, (sum >> 32) as i32);
+    }
+}
+
+/// ( sec top borrow -- diff borrow ): top - sec - borrow, like `sub`, pushing the borrow out on top.
+pub fn subb(m: &mut Machine, _: i32) {
+    if let (Some(bin), Some(top), Some(sec)) = (pop(m), pop(m), pop(m)) {
+        let diff = (top as u32 as i64) - (sec as u32 as i64) - (bin != 0) as i64;
+        push(m, diff as i32);
+        push(m, (diff < 0) as i32);
+    }
+}
+
+pub fn mulh(m: &mut Machine, _: i32) {
+    if let (Some(top), Some(sec)) = (pop(m), pop(m)) {
+        let prod = (top as i64) * (sec as i64);
+        push(m, (prod >> 32) as i32);
+    }
+}
+
+pub fn mulhu(m: &mut Machine, _: i32) {
+    if let (Some(top), Some(sec)) = (pop(m), pop(m)) {
+        let prod = (top as u32 as u64) * (sec as u32 as u64);
+        push(m, (prod >> 32) as i32);
+    }
+}
+
+// 64-bit values are kept on the stack as a (lo, hi) pair, hi on top.
+
+fn pop_pair(m: &mut Machine) -> Option<i64> {
+    if let (Some(hi), Some(lo)) = (pop(m), pop(m)) {
+        Some(((hi as i64) << 32) | (lo as u32 as i64))
+    } else {
+        None
+    }
+}
+
+fn push_pair(m: &mut Machine, val: i64) {
+    push(m, val as i32);
+    push(m, (val >> 32) as i32);
+}
+
+macro_rules! pair_op_funcs {
+    ( $($name:ident ($method:ident));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, _: i32) {
+                if let (Some(top), Some(sec)) = (pop_pair(m), pop_pair(m)) {
+                    push_pair(m, top.$method(sec));
+                }
+            }
+        )+
+    }
+}
+
+pair_op_funcs! {
+    add64 ( wrapping_add );
+    sub64 ( wrapping_sub );
+    mul64 ( wrapping_mul );
+}
+
+fn check_divisor(m: &mut Machine, divisor: i32) -> bool {
+    if divisor == 0 {
+        let pc = m.getpc();
+        m.set_error(MachineError::DivisionByZero { pc });
+        return false;
+    }
+    true
+}
+
+macro_rules! division_funcs {
+    ( $($name:ident ($method:ident $ty:ty));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, _: i32) {
+                if let (Some(top), Some(sec)) = (pop(m), pop(m)) {
+                    if check_divisor(m, sec) {
+                        push(m, (top as $ty).$method(sec as $ty) as i32);
+                    }
+                }
+            }
+        )+
+    }
+}
+
+division_funcs! {
+    div  ( wrapping_div i32 );
+    rem  ( wrapping_rem i32 );
+    divu ( wrapping_div u32 );
+    remu ( wrapping_rem u32 );
+}
+
+macro_rules! division_imm_funcs {
+    ( $($name:ident ($method:ident $ty:ty));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, imm: i32) {
+                if !check_divisor(m, imm) {
+                    return;
+                }
+                if let Some(top) = pop(m) {
+                    push(m, (top as $ty).$method(imm as $ty) as i32);
+                }
+            }
+        )+
+    }
+}
+
+division_imm_funcs! {
+    divi  ( wrapping_div i32 );
+    remi  ( wrapping_rem i32 );
+    divui ( wrapping_div u32 );
+    remui ( wrapping_rem u32 );
+}
+
+macro_rules! unsigned_branch_cmp_funcs {
+    ( $($name:ident ($cmp:tt));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, offset: i32) {
+                if let (Some(top), Some(sec)) = (pop(m), pop(m)) {
+                    if (sec as u32) $cmp (top as u32) {
+                        jump(m, offset);
+                    }
+                }
+            }
+        )+
+    }
+}
+
+unsigned_branch_cmp_funcs! {
+    bltu ( < );
+    bleu ( <= );
+    bgtu ( > );
+    bgeu ( >= );
+}
+
+macro_rules! set_cmp_funcs {
+    ( $($name:ident ($cmp:tt $ty:ty));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, _: i32) {
+                if let (Some(top), Some(sec)) = (pop(m), pop(m)) {
+                    let res = (sec as $ty) $cmp (top as $ty);
+                    push(m, res as i32);
+                }
+            }
+        )+
+    }
+}
+
+set_cmp_funcs! {
+    slt  ( <  i32 );
+    sltu ( <  u32 );
+    seq  ( == i32 );
+}
+
+fn check_shamt(m: &mut Machine, shamt: i32) -> bool {
+    if !(0..32).contains(&shamt) {
+        let pc = m.getpc();
+        m.set_error(MachineError::InvalidShiftAmount { pc, shamt });
+        return false;
+    }
+    true
+}
+
 pub fn sar(m: &mut Machine, _: i32) {
     if let (Some(shamt), Some(val)) = (pop(m), pop(m)) {
-        let val = val >> shamt;
-        push(m, val);
+        if check_shamt(m, shamt) {
+            let val = val >> shamt;
+            push(m, val);
+        }
     }
 }
 
 pub fn sari(m: &mut Machine, shamt: i32) {
+    if !check_shamt(m, shamt) {
+        return;
+    }
     if let Some(top) = pop(m) {
         let top = top >> shamt;
         push(m, top);
@@ -212,6 +666,9 @@ macro_rules! logical_shift_imm_funcs {
     ( $($name:ident ($shop:tt));+; ) => {
         $(
             pub fn $name(m: &mut Machine, shamt: i32) {
+                if !check_shamt(m, shamt) {
+                    return;
+                }
                 if let Some(top) = pop(m) {
                     let top1 = top as u32;
                     let top2 = (top1 $shop shamt);
@@ -233,10 +690,12 @@ macro_rules! logical_shift_funcs {
         $(
             pub fn $name(m: &mut Machine, _: i32) {
                 if let (Some(shamt), Some(val)) = (pop(m), pop(m)) {
-                    let val1 = val as u32;
-                    let val2 = val1 $shop shamt;
-                    let val3 = val2 as i32;
-                    push(m, val3);
+                    if check_shamt(m, shamt) {
+                        let val1 = val as u32;
+                        let val2 = val1 $shop shamt;
+                        let val3 = val2 as i32;
+                        push(m, val3);
+                    }
                 }
             }
         )+
@@ -248,6 +707,177 @@ logical_shift_funcs! {
     shr ( >> );
 }
 
+macro_rules! rotate_funcs {
+    ( $($name:ident ($method:ident));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, _: i32) {
+                if let (Some(shamt), Some(val)) = (pop(m), pop(m)) {
+                    push(m, val.$method(shamt as u32));
+                }
+            }
+        )+
+    };
+}
+
+rotate_funcs! {
+    rotl ( rotate_left );
+    rotr ( rotate_right );
+}
+
+macro_rules! rotate_imm_funcs {
+    ( $($name:ident ($method:ident));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, shamt: i32) {
+                if let Some(top) = pop(m) {
+                    push(m, top.$method(shamt as u32));
+                }
+            }
+        )+
+    };
+}
+
+rotate_imm_funcs! {
+    rotli ( rotate_left );
+    rotri ( rotate_right );
+}
+
+macro_rules! bit_count_funcs {
+    ( $($name:ident ($method:ident));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, _: i32) {
+                if let Some(top) = pop(m) {
+                    push(m, top.$method() as i32);
+                }
+            }
+        )+
+    };
+}
+
+bit_count_funcs! {
+    popcnt ( count_ones );
+    clz    ( leading_zeros );
+    ctz    ( trailing_zeros );
+}
+
+pub fn bswap(m: &mut Machine, _: i32) {
+    if let Some(top) = pop(m) {
+        push(m, top.swap_bytes());
+    }
+}
+
+// Floating point ops treat words as IEEE-754 single-precision bit patterns.
+
+fn pop_float(m: &mut Machine) -> Option<f32> {
+    pop(m).map(|bits| f32::from_bits(bits as u32))
+}
+
+fn push_float(m: &mut Machine, val: f32) {
+    push(m, val.to_bits() as i32);
+}
+
+macro_rules! float_binary_op_funcs {
+    ( $($name:ident ($operator:tt));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, _: i32) {
+                if let (Some(top), Some(sec)) = (pop_float(m), pop_float(m)) {
+                    push_float(m, top $operator sec);
+                }
+            }
+        )+
+    }
+}
+
+float_binary_op_funcs! {
+    fadd ( + );
+    fsub ( - );
+    fmul ( * );
+    fdiv ( / );
+}
+
+macro_rules! float_unary_op_funcs {
+    ( $($name:ident ($method:ident));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, _: i32) {
+                if let Some(top) = pop_float(m) {
+                    push_float(m, top.$method());
+                }
+            }
+        )+
+    }
+}
+
+float_unary_op_funcs! {
+    fsqrt ( sqrt );
+    fabs  ( abs );
+    fneg  ( neg );
+}
+
+macro_rules! float_set_cmp_funcs {
+    ( $($name:ident ($cmp:tt));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, _: i32) {
+                if let (Some(top), Some(sec)) = (pop_float(m), pop_float(m)) {
+                    push(m, (sec $cmp top) as i32);
+                }
+            }
+        )+
+    }
+}
+
+float_set_cmp_funcs! {
+    feq ( == );
+    flt ( < );
+    fle ( <= );
+}
+
+macro_rules! float_branch_cmp_funcs {
+    ( $($name:ident ($cmp:tt));+; ) => {
+        $(
+            pub fn $name(m: &mut Machine, offset: i32) {
+                if let (Some(top), Some(sec)) = (pop_float(m), pop_float(m)) {
+                    if sec $cmp top {
+                        jump(m, offset);
+                    }
+                }
+            }
+        )+
+    }
+}
+
+float_branch_cmp_funcs! {
+    fbeq ( == );
+    fbne ( != );
+    fblt ( < );
+    fble ( <= );
+    fbgt ( > );
+    fbge ( >= );
+}
+
+pub fn itof(m: &mut Machine, _: i32) {
+    if let Some(top) = pop(m) {
+        push_float(m, top as f32);
+    }
+}
+
+pub fn utof(m: &mut Machine, _: i32) {
+    if let Some(top) = pop(m) {
+        push_float(m, top as u32 as f32);
+    }
+}
+
+/// Truncates towards zero, saturating at the bounds of `i32`. NaN converts to 0.
+pub fn ftoi(m: &mut Machine, _: i32) {
+    if let Some(top) = pop_float(m) {
+        push(m, top as i32);
+    }
+}
+
+pub fn ftou(m: &mut Machine, _: i32) {
+    if let Some(top) = pop_float(m) {
+        push(m, top as u32 as i32);
+    }
+}
+
 // --- END OP FUNCTIONS ---
 
 pub type OpFunction = fn(&mut Machine, i32);
@@ -308,6 +938,19 @@ def_op_list![
     addi subi muli divi remi andi ori xori sari shli shri
     beq bne blt ble bge bgt
     ecall ebreak
+    jumpr jalr
+    dup swap over rot drop pick
+    divu remu divui remui bltu bleu bgtu bgeu slt sltu seq
+    rotl rotr rotli rotri popcnt clz ctz bswap
+    addo subo mulo addoi suboi muloi
+    addc subb mulh mulhu add64 sub64 mul64
+    fadd fsub fmul fdiv fsqrt fabs fneg feq flt fle
+    fbeq fbne fblt fble fbgt fbge itof utof ftoi ftou
+    pushhi orlo
+    loadb storeb loadh storeh
+    memcpy memmove memset memcmp
+    rettrap
+    ei di iret
 ];
 
 pub const OP_INVALID: &'static Operation = &OP_LIST[0];
@@ -3,7 +3,7 @@ use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::ops::Range;
 
-use crate::encoder::Encoder;
+use crate::encoder::{Encoder, ARG_RANGE};
 use crate::isa::{Inst, OP_INVALID};
 use crate::linker::LinkerError::MissingTarget;
 use crate::mem::inst_loc_to_addr;
@@ -80,6 +80,7 @@ pub enum LinkerError {
     NeedToDefineEntryLabel,
     MissingTarget(Inst, Vec<String>),
     NoSuchOp(i32, String),
+    ArgOutOfRange(Inst, i32),
 }
 
 impl Display for LinkerError {
@@ -88,6 +89,9 @@ impl Display for LinkerError {
             MissingTarget(inst, target) => {
                 write!(f, "MissingTarget({} \"{:?}\")", inst, target)
             }
+            LinkerError::ArgOutOfRange(inst, value) => {
+                write!(f, "ArgOutOfRange({} value {})", inst, value)
+            }
             other => write!(f, "{:?}", other),
         }
     }
@@ -101,9 +105,15 @@ pub enum TargetTerm {
 
 pub type RelocationTarget = Vec<TargetTerm>;
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RelocationKind {
+    PcRelative,
+    Absolute,
+}
+
 pub struct Linker {
     instructions: Vec<Inst>,
-    to_relocate: HashMap<usize, RelocationTarget>,
+    to_relocate: HashMap<usize, (RelocationKind, RelocationTarget)>,
     resolved_targets: HashMap<i32, ResolvedTarget>,
 
     top_level_labels: HashMap<String, TopLevelLabel>,
@@ -164,10 +174,17 @@ impl Linker {
     }
 
     pub fn add_placeholder_inst(&mut self, opname: &str, target: RelocationTarget) {
-        self.to_relocate.insert(self.next_inst_loc(), target);
+        self.to_relocate
+            .insert(self.next_inst_loc(), (RelocationKind::PcRelative, target));
         self.add_inst(opname, 0);
     }
 
+    pub fn add_placeholder_word(&mut self, target: RelocationTarget) {
+        self.to_relocate
+            .insert(self.next_inst_loc(), (RelocationKind::Absolute, target));
+        self.add_raw_word(0);
+    }
+
     pub fn add_top_level_label(&mut self, name: &str) {
         if self.cur_frame_name != "" {
             self.end_current_frame();
@@ -240,13 +257,18 @@ impl Linker {
 
     pub fn add_raw_word(&mut self, value: i32) {
         let addr = self.next_inst_addr();
-        let inst = Inst {
+        self.frame_for_inst_addr
+            .insert(addr, self.cur_frame_name.clone());
+        self.instructions.push(Linker::raw_word_inst(addr, value));
+    }
+
+    fn raw_word_inst(addr: i32, value: i32) -> Inst {
+        Inst {
             addr: Some(addr),
             op: OP_INVALID,
             opcode: ((value as u32 & 0xff000000) >> 24) as u8,
             arg: (value & 0x00ffffff) as i32,
-        };
-        self.instructions.push(inst);
+        }
     }
 
     pub fn finish(&mut self) {
@@ -263,7 +285,12 @@ impl Linker {
         );
     }
 
-    fn resolve_ident(&self, inst_loc: usize, name: &str) -> Option<(i32, LabelType)> {
+    fn resolve_ident(
+        &self,
+        inst_loc: usize,
+        name: &str,
+        kind: RelocationKind,
+    ) -> Option<(i32, LabelType)> {
         use LabelType::*;
         // Global mapping
         if let Some(&value) = self.global_mappings.get(name) {
@@ -272,7 +299,7 @@ impl Linker {
         // Top level label
         let inst_addr = inst_loc_to_addr(inst_loc);
         if let Some(label) = self.top_level_labels.get(name) {
-            let value = Linker::pc_relative(label.addr_range.start, inst_addr);
+            let value = Linker::code_ref(label.addr_range.start, inst_addr, kind);
             return Some((value, TopLevelLabel));
         }
         // Local frame
@@ -280,7 +307,7 @@ impl Linker {
         let frame = self.top_level_labels.get(frame_name)?;
         // Local code (inner label)
         if let Some(&addr) = frame.inner_labels.get(name) {
-            let value = Linker::pc_relative(addr, inst_addr);
+            let value = Linker::code_ref(addr, inst_addr, kind);
             return Some((value, InnerLabel));
         }
         // Local var
@@ -293,6 +320,7 @@ impl Linker {
     fn resolve(
         &self,
         inst_loc: usize,
+        kind: RelocationKind,
         target: &RelocationTarget,
     ) -> Result<ResolvedTarget, Vec<String>> {
         let inst_addr = inst_loc_to_addr(inst_loc);
@@ -304,7 +332,7 @@ impl Linker {
         let (resolutions, unresolved): (Vec<_>, Vec<_>) = target
             .iter()
             .map(|t| match t {
-                TargetTerm::Ident(name) => self.resolve_ident(inst_loc, name).ok_or(name),
+                TargetTerm::Ident(name) => self.resolve_ident(inst_loc, name, kind).ok_or(name),
                 TargetTerm::Literal(x) => Ok((*x, LabelType::_Literal)),
             })
             .partition(Result::is_ok);
@@ -335,11 +363,25 @@ impl Linker {
 
     pub fn relocate(&mut self) -> Result<(), Vec<(usize, Vec<String>)>> {
         let mut unrelocated = Vec::<(usize, Vec<String>)>::new();
-        for (&inst_loc, target) in self.to_relocate.iter() {
+        for (&inst_loc, (kind, target)) in self.to_relocate.iter() {
             let inst_addr = inst_loc_to_addr(inst_loc);
-            match self.resolve(inst_loc, target) {
+            match self.resolve(inst_loc, *kind, target) {
                 Ok(resolved) => {
-                    self.instructions[inst_loc].arg = resolved.value;
+                    match kind {
+                        RelocationKind::PcRelative => {
+                            self.instructions[inst_loc].arg = resolved.value;
+                            if !ARG_RANGE.contains(&resolved.value) {
+                                self.errors.push(LinkerError::ArgOutOfRange(
+                                    self.instructions[inst_loc],
+                                    resolved.value,
+                                ));
+                            }
+                        }
+                        RelocationKind::Absolute => {
+                            self.instructions[inst_loc] =
+                                Linker::raw_word_inst(inst_addr, resolved.value);
+                        }
+                    }
                     self.resolved_targets.insert(inst_addr, resolved);
                 }
                 Err(unresolved) => unrelocated.push((inst_loc, unresolved)),
@@ -353,9 +395,10 @@ impl Linker {
     }
 
     pub fn link_binary(&mut self) -> Result<Vec<i32>, Vec<LinkerError>> {
+        let relocated = self.relocate();
         let mut errors: Vec<LinkerError> = self.errors.clone();
 
-        if let Err(unrelocated) = self.relocate() {
+        if let Err(unrelocated) = relocated {
             errors.extend(
                 unrelocated
                     .into_iter()
@@ -376,6 +419,13 @@ impl Linker {
     fn pc_relative(target_addr: i32, inst_addr: i32) -> i32 {
         target_addr - inst_addr - 1
     }
+
+    fn code_ref(target_addr: i32, inst_addr: i32, kind: RelocationKind) -> i32 {
+        match kind {
+            RelocationKind::PcRelative => Linker::pc_relative(target_addr, inst_addr),
+            RelocationKind::Absolute => target_addr,
+        }
+    }
 }
 
 impl Display for Linker {
@@ -3,6 +3,7 @@ use std::error::Error;
 use std::fmt::{Debug, Formatter, Write as FmtWrite};
 use std::io::Write;
 use std::ops::Range;
+use std::thread;
 use std::{fmt, io};
 
 use MachineError::*;
@@ -10,9 +11,11 @@ use MachineStatus::*;
 
 use crate::encoder::Encoder;
 use crate::environment::Environment;
+use crate::isa;
 use crate::isa::Inst;
 use crate::linker::{DebugInfo, ResolvedTarget};
 use crate::mem::{addrs, inst_loc_to_addr, segs, Memory};
+use crate::threads::{self, SchedRequest, Thread, ThreadState, Threads};
 use crate::util;
 
 type ErrorResult<T> = Result<T, Box<dyn Error>>;
@@ -28,36 +31,128 @@ pub enum MachineError {
     CannotDecodeInst(i32),
     StackAccessBeyondSP { sp: i32, addr: i32 },
     StackAccessSegFault { addr: i32 },
+    StackUnderflow { sp: i32, depth: i32 },
     CodeAccessSegFault { addr: i32 },
     ProgramExit(i32),
     NoSuchEnvCall(i32),
+    DivisionByZero { pc: i32 },
+    InvalidShiftAmount { pc: i32, shamt: i32 },
+    ArithmeticOverflow { pc: i32 },
     LoadAddressOutOfBounds { addr: i32 },
     StoreAddressOutOfBounds { addr: i32 },
+    MisalignedAccess { byte_addr: i32 },
     AttemptedWriteToCodeSegment { addr: i32 },
+    StackOverflow { newsp: i32 },
+    Deadlock,
+    // a replayed run made an ecall the recording doesn't have next
+    ReplayDiverged,
     MaxCyclesReached,
 }
 
+/// Codes pushed for trap handlers, available in assembly as `.fault.<name>`, by variant name.
+pub const FAULT_CODES: &[(&str, i32)] = &[
+    ("IllegalSPReductionBelowMin", 1),
+    ("IllegalDirectWriteSP", 2),
+    ("IllegalDirectWritePC", 3),
+    ("ImminentPCSegFault", 4),
+    ("InvalidInstruction", 5),
+    ("CannotDecodeInst", 6),
+    ("StackAccessBeyondSP", 7),
+    ("StackAccessSegFault", 8),
+    ("StackUnderflow", 9),
+    ("CodeAccessSegFault", 10),
+    ("NoSuchEnvCall", 11),
+    ("DivisionByZero", 12),
+    ("InvalidShiftAmount", 13),
+    ("ArithmeticOverflow", 14),
+    ("LoadAddressOutOfBounds", 15),
+    ("StoreAddressOutOfBounds", 16),
+    ("MisalignedAccess", 17),
+    ("AttemptedWriteToCodeSegment", 18),
+    ("StackOverflow", 19),
+];
+
+impl MachineError {
+    /// The variant's name, as used in `FAULT_CODES`.
+    fn name(&self) -> &'static str {
+        match self {
+            IllegalSPReductionBelowMin { .. } => "IllegalSPReductionBelowMin",
+            IllegalDirectWriteSP => "IllegalDirectWriteSP",
+            IllegalDirectWritePC => "IllegalDirectWritePC",
+            ImminentPCSegFault { .. } => "ImminentPCSegFault",
+            InvalidInstruction => "InvalidInstruction",
+            CannotDecodeInst(_) => "CannotDecodeInst",
+            StackAccessBeyondSP { .. } => "StackAccessBeyondSP",
+            StackAccessSegFault { .. } => "StackAccessSegFault",
+            StackUnderflow { .. } => "StackUnderflow",
+            CodeAccessSegFault { .. } => "CodeAccessSegFault",
+            ProgramExit(_) => "ProgramExit",
+            NoSuchEnvCall(_) => "NoSuchEnvCall",
+            DivisionByZero { .. } => "DivisionByZero",
+            InvalidShiftAmount { .. } => "InvalidShiftAmount",
+            ArithmeticOverflow { .. } => "ArithmeticOverflow",
+            LoadAddressOutOfBounds { .. } => "LoadAddressOutOfBounds",
+            StoreAddressOutOfBounds { .. } => "StoreAddressOutOfBounds",
+            MisalignedAccess { .. } => "MisalignedAccess",
+            AttemptedWriteToCodeSegment { .. } => "AttemptedWriteToCodeSegment",
+            StackOverflow { .. } => "StackOverflow",
+            Deadlock => "Deadlock",
+            ReplayDiverged => "ReplayDiverged",
+            MaxCyclesReached => "MaxCyclesReached",
+        }
+    }
+
+    /// The code passed to a trap handler, or None if the error can't be trapped.
+    /// Untrappable errors aren't listed in `FAULT_CODES`.
+    pub fn fault_code(&self) -> Option<i32> {
+        let name = self.name();
+        FAULT_CODES
+            .iter()
+            .find(|(fault, _)| *fault == name)
+            .map(|(_, code)| *code)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum MachineStatus {
     Idle,
     Running,
     Debugging,
+    /// Every thread is waiting on another machine in the cluster
+    Blocked,
     Stopped,
     Error(MachineError),
 }
 
+#[derive(Default)]
+pub(crate) struct Interrupts {
+    pub(crate) enabled: bool,
+    pub(crate) vector: Option<i32>,
+    /// Cycles between timer interrupts, 0 if the timer is off
+    pub(crate) timer_interval: usize,
+    pub(crate) timer_next: usize,
+}
+
 pub struct Machine {
     mem: Memory,
+    // (addr, old value) for every store made by the current instruction
+    journal: Vec<(i32, i32)>,
 
     pub(crate) status: MachineStatus,
     ncycles: usize,
     encoder: Encoder,
 
     pub(crate) env: Environment,
+    pub(crate) trap_handler: Option<i32>,
+    in_trap: bool,
+    pub(crate) interrupts: Interrupts,
+    pub(crate) threads: Threads,
 
     pub debug_info: DebugInfo,
     pub max_cycles: usize,
     pub debug_on_error: bool,
+    pub trap_overflow: bool,
+    pub show_floats: bool,
 }
 
 impl Machine {
@@ -121,6 +216,7 @@ impl Machine {
             self.set_error(StoreAddressOutOfBounds { addr });
             return;
         }
+        self.journal.push((addr, self.mem[addr]));
         self.mem[addr] = val;
     }
 
@@ -132,6 +228,71 @@ impl Machine {
         self.mem[addr]
     }
 
+    pub(crate) fn journal_len(&self) -> usize {
+        self.journal.len()
+    }
+
+    /// (addr, new value) for every store the current instruction made after the journal had `start` entries.
+    pub(crate) fn stores_since(&self, start: usize) -> Vec<(i32, i32)> {
+        self.journal[start..]
+            .iter()
+            .map(|&(addr, _)| (addr, self.mem[addr]))
+            .collect()
+    }
+
+    /// Reads memory without faulting, for tools looking at the machine from outside.
+    pub fn peek(&self, addr: i32) -> Option<i32> {
+        if segs::ADDR_SPACE.contains(&addr) {
+            Some(self.mem[addr])
+        } else {
+            None
+        }
+    }
+
+    pub fn peek_byte(&self, byte_addr: i32) -> Option<i32> {
+        let word = self.peek(byte_addr.div_euclid(4))?;
+        Some((word >> (24 - 8 * byte_addr.rem_euclid(4))) & 0xff)
+    }
+
+    // Byte addresses select a byte within a word: `byte_addr = word_addr * 4 + index`,
+    // with index 0 being the most significant byte, like strings packed by `.word`.
+
+    pub fn load_byte(&mut self, byte_addr: i32) -> i32 {
+        let word = self.load(byte_addr.div_euclid(4));
+        let shift = 24 - 8 * byte_addr.rem_euclid(4);
+        (word >> shift) & 0xff
+    }
+
+    pub fn store_byte(&mut self, byte_addr: i32, val: i32) {
+        let addr = byte_addr.div_euclid(4);
+        let shift = 24 - 8 * byte_addr.rem_euclid(4);
+        let word = self.load(addr);
+        let mask = 0xff << shift;
+        self.store(addr, (word & !mask) | ((val & 0xff) << shift));
+    }
+
+    pub fn load_half(&mut self, byte_addr: i32) -> i32 {
+        if byte_addr % 2 != 0 {
+            self.set_error(MisalignedAccess { byte_addr });
+            return 0;
+        }
+        let word = self.load(byte_addr.div_euclid(4));
+        let shift = 16 - 8 * byte_addr.rem_euclid(4);
+        (word >> shift) & 0xffff
+    }
+
+    pub fn store_half(&mut self, byte_addr: i32, val: i32) {
+        if byte_addr % 2 != 0 {
+            self.set_error(MisalignedAccess { byte_addr });
+            return;
+        }
+        let addr = byte_addr.div_euclid(4);
+        let shift = 16 - 8 * byte_addr.rem_euclid(4);
+        let word = self.load(addr);
+        let mask = 0xffff << shift;
+        self.store(addr, (word & !mask) | ((val & 0xffff) << shift));
+    }
+
     pub fn setpc(&mut self, newpc: i32) {
         if !self.code_access_ok(newpc) {
             self.set_error(ImminentPCSegFault { newpc });
@@ -140,14 +301,33 @@ impl Machine {
         self.store(addrs::PC, newpc);
     }
 
+    /// Jumps to an absolute code address, so that `target` is the next instruction executed.
+    pub fn jump_to(&mut self, target: i32) {
+        if !self.code_access_ok(target) {
+            self.set_error(ImminentPCSegFault { newpc: target });
+            return;
+        }
+        self.store(addrs::PC, target - 1);
+    }
+
     pub fn setsp(&mut self, newsp: i32) {
-        if newsp < addrs::INIT_SP {
+        let stack = self.stack_range();
+        if newsp < stack.start {
             self.set_error(IllegalSPReductionBelowMin { newsp });
             return;
         }
+        if newsp > stack.end {
+            self.set_error(StackOverflow { newsp });
+            return;
+        }
         self.store(addrs::SP, newsp);
     }
 
+    /// The stack region of the current thread.
+    pub fn stack_range(&self) -> Range<i32> {
+        self.threads.list[self.threads.current].stack.clone()
+    }
+
     pub fn breakpoint(&mut self) {
         println!(
             "{}",
@@ -205,6 +385,18 @@ impl Machine {
                 "st" => {
                     println!("{:?}", self);
                 }
+                "th" => match int_args[..] {
+                    [id] if (id as usize) < self.threads.list.len() => {
+                        self.load_thread(id as usize);
+                        println!("{}", self.thread_dump());
+                    }
+                    [] => println!("{}", self.thread_dump()),
+                    _ => println!("format: th [id]"),
+                },
+                "fl" => {
+                    self.show_floats = !self.show_floats;
+                    println!("float view {}", if self.show_floats { "on" } else { "off" });
+                }
                 "x" => {
                     self.set_status(Stopped);
                     return Ok(());
@@ -276,6 +468,32 @@ impl Machine {
         Ok(out)
     }
 
+    pub fn thread_dump(&self) -> String {
+        self.threads
+            .list
+            .iter()
+            .map(|thread| {
+                let current = thread.id == self.threads.current;
+                let (pc, sp) = if current {
+                    (self.getpc(), self.getsp())
+                } else {
+                    (thread.pc, thread.sp)
+                };
+                format!(
+                    "{} {:3} pc {:5x} sp {:4x} stack {:4x}..{:4x} {:?}",
+                    if current { "*" } else { " " },
+                    thread.id,
+                    pc,
+                    sp,
+                    thread.stack.start,
+                    thread.stack.end,
+                    thread.state
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
     pub fn frame_dump(&self) -> String {
         let fp = self.mem[addrs::FP];
         self.stack_dump(fp - 8..self.getsp())
@@ -350,11 +568,12 @@ impl Machine {
                     "".to_string()
                 };
                 format!(
-                    "{:01x} {:04x}: {:8x} [{:12}]{}",
+                    "{:01x} {:04x}: {:8x} [{:12}]{}{}",
                     addr >> 16,
                     addr & 0xffff,
                     val,
                     val,
+                    self.formatted_float(val),
                     maybe_char
                 )
             })
@@ -367,19 +586,39 @@ impl Machine {
             return None;
         }
         let val = self.mem[addr];
-        Some(format!("{:04x}: {:8x} [{:12}]", addr, val, val))
+        Some(format!(
+            "{:04x}: {:8x} [{:12}]{}",
+            addr,
+            val,
+            val,
+            self.formatted_float(val)
+        ))
+    }
+
+    fn formatted_float(&self, val: i32) -> String {
+        if !self.show_floats {
+            return "".to_string();
+        }
+        format!(" [{:>14?}]", f32::from_bits(val as u32))
     }
 
     pub fn new() -> Machine {
         Machine {
             mem: Memory::new(),
+            journal: Vec::new(),
             env: Default::default(),
+            trap_handler: None,
+            in_trap: false,
+            interrupts: Default::default(),
+            threads: Default::default(),
             encoder: Encoder::new(),
             debug_info: DebugInfo::new(),
             status: Idle,
             ncycles: 0,
             debug_on_error: true,
             max_cycles: 1_000_000,
+            trap_overflow: false,
+            show_floats: false,
         }
     }
 
@@ -395,9 +634,28 @@ impl Machine {
     }
 
     pub fn set_error(&mut self, error: MachineError) {
+        if let Error(_) = self.status {
+            // keep the first fault, later ones are usually a consequence of it
+            return;
+        }
         self.set_status(Error(error))
     }
 
+    pub fn has_error(&self) -> bool {
+        matches!(self.status, Error(_))
+    }
+
+    /// Charges extra cycles to the current instruction, for ops that do work proportional to their input.
+    pub fn add_cycles(&mut self, n: i32) {
+        if n > 0 {
+            self.ncycles += n as usize;
+        }
+    }
+
+    pub fn ncycles(&self) -> usize {
+        self.ncycles
+    }
+
     pub fn is_running(&self) -> bool {
         match self.status {
             Running | Debugging => true,
@@ -418,24 +676,251 @@ impl Machine {
     }
 
     pub fn cycle(&mut self) {
+        self.env.step_children();
+        self.journal.clear();
         let inst = match self.fetch_inst(self.getpc()) {
             Err(e) => {
                 self.set_error(e);
+                self.trap();
                 return;
             }
             Ok(inst) => inst,
         };
         (inst.op.func)(self, inst.arg);
         self.setpc(self.getpc() + 1);
+        if let Error(_) = self.status {
+            // faults are precise: the instruction takes no effect, PC included
+            self.rollback();
+            self.trap();
+            return;
+        }
+        let request = self.threads.request.take();
+        if request == Some(SchedRequest::Block) {
+            self.rollback();
+            self.block_thread();
+            return;
+        }
+        self.threads.blocked_streak = 0;
+        self.threads.waiting_on_host = false;
         self.ncycles += 1;
+        self.threads.slice_used += 1;
+        if request == Some(SchedRequest::Yield) || self.threads.slice_used >= threads::QUANTUM {
+            self.switch_thread();
+        }
+        self.check_timer();
         if self.status == Debugging {
             self.debug_cycle().unwrap();
         }
-        if self.ncycles == self.max_cycles {
+        if self.ncycles >= self.max_cycles {
             self.set_error(MaxCyclesReached);
         }
     }
 
+    /// Hands the current error to the guest's trap handler, if one is registered, by pushing
+    /// the fault code and the faulting PC and jumping to the handler. Faults inside the
+    /// handler are not trapped again.
+    fn trap(&mut self) {
+        let code = match (&self.status, self.trap_handler) {
+            (Error(err), Some(_)) if !self.in_trap => match err.fault_code() {
+                Some(code) => code,
+                None => return,
+            },
+            _ => return,
+        };
+        let error = self.status.clone();
+        let pc = self.getpc();
+        self.set_status(Running);
+        isa::push(self, code);
+        isa::push(self, pc);
+        if self.has_error() {
+            self.rollback();
+            self.set_status(error);
+            return;
+        }
+        self.in_trap = true;
+        self.store(addrs::PC, self.trap_handler.unwrap());
+    }
+
+    pub fn return_from_trap(&mut self, pc: i32) {
+        self.jump_to(pc);
+        if !self.has_error() {
+            self.in_trap = false;
+        }
+    }
+
+    /// Starts a thread running `entry(arg)`, returning its id, or None if there is no
+    /// stack left for it. Returning from `entry` ends the thread.
+    pub fn spawn_thread(&mut self, entry: i32, arg: i32) -> Option<usize> {
+        let main_sp = if self.threads.current == threads::MAIN_THREAD {
+            self.getsp()
+        } else {
+            self.threads.list[threads::MAIN_THREAD].sp
+        };
+        if main_sp > threads::THREAD_STACKS.start {
+            return None;
+        }
+        let stack = self.threads.free_stack()?;
+        self.threads.list[threads::MAIN_THREAD].stack.end = threads::THREAD_STACKS.start;
+        // [arg retval retaddr], as if called by `.call`
+        self.store(stack.start, arg);
+        self.store(stack.start + 1, 0);
+        self.store(stack.start + 2, threads::THREAD_RETURN);
+        let id = self.threads.list.len();
+        self.threads.list.push(Thread {
+            id,
+            state: ThreadState::Runnable,
+            pc: entry,
+            sp: stack.start + 3,
+            fp: addrs::INIT_FP,
+            stack,
+        });
+        Some(id)
+    }
+
+    pub fn is_spawned_thread(&self) -> bool {
+        self.threads.current != threads::MAIN_THREAD
+    }
+
+    pub fn exit_thread(&mut self, retval: i32) {
+        self.threads.list[self.threads.current].state = ThreadState::Finished(retval);
+        self.threads.request = Some(SchedRequest::Yield);
+    }
+
+    pub fn yield_thread(&mut self) {
+        self.threads.request = Some(SchedRequest::Yield);
+    }
+
+    /// Makes the current instruction wait: it is undone and retried when the thread next runs.
+    pub fn block(&mut self) {
+        self.threads.request = Some(SchedRequest::Block);
+    }
+
+    /// Like `block`, for waits on the world outside the VM such as a socket. Only the host can
+    /// end them, so they never count as a deadlock.
+    pub fn block_on_host(&mut self) {
+        self.threads.waiting_on_host = true;
+        self.block();
+    }
+
+    /// Whether a thread has been waiting on the host since the machine last got anywhere,
+    /// so it may still get further without help from inside the VM.
+    pub fn is_waiting_on_host(&self) -> bool {
+        self.threads.waiting_on_host
+    }
+
+    pub fn is_blocking(&self) -> bool {
+        self.threads.request == Some(SchedRequest::Block)
+    }
+
+    fn block_thread(&mut self) {
+        self.threads.blocked_streak += 1;
+        if self.threads.blocked_streak >= self.threads.num_runnable() {
+            if self.threads.waiting_on_host {
+                // keep polling, without hogging the host's cpu
+                self.threads.blocked_streak = 0;
+                thread::yield_now();
+                return;
+            }
+            if self.env.cluster.is_some() || self.env.is_child {
+                // another machine may still unblock us, whoever runs us decides if it's a deadlock
+                self.threads.blocked_streak = 0;
+                self.set_status(Blocked);
+                return;
+            }
+            if self.env.children_progressed {
+                // a child may still unblock us
+                self.threads.blocked_streak = 0;
+                return;
+            }
+            // every thread is waiting on another
+            self.set_error(Deadlock);
+            return;
+        }
+        self.switch_thread();
+    }
+
+    fn switch_thread(&mut self) {
+        self.threads.slice_used = 0;
+        match self.threads.next_runnable() {
+            None => self.set_status(Stopped),
+            Some(id) => self.load_thread(id),
+        }
+    }
+
+    fn load_thread(&mut self, id: usize) {
+        let cur = self.threads.current;
+        if id == cur {
+            return;
+        }
+        let thread = &mut self.threads.list[cur];
+        thread.pc = self.mem[addrs::PC];
+        thread.sp = self.mem[addrs::SP];
+        thread.fp = self.mem[addrs::FP];
+        let next = &self.threads.list[id];
+        self.mem[addrs::PC] = next.pc;
+        self.mem[addrs::SP] = next.sp;
+        self.mem[addrs::FP] = next.fp;
+        self.threads.current = id;
+    }
+
+    pub fn set_timer(&mut self, interval: usize) {
+        self.interrupts.timer_interval = interval;
+        self.interrupts.timer_next = self.ncycles + interval;
+    }
+
+    fn check_timer(&mut self) {
+        let ints = &mut self.interrupts;
+        if ints.timer_interval == 0 || self.ncycles < ints.timer_next {
+            return;
+        }
+        ints.timer_next = self.ncycles + ints.timer_interval;
+        if ints.enabled && ints.vector.is_some() {
+            self.interrupt();
+        }
+    }
+
+    /// Enters the interrupt vector with interrupts disabled. The interrupted state is saved as
+    /// `[.. status pc]`, where `pc` is the next instruction to run and bit 0 of `status` is set
+    /// if interrupts were enabled. `iret` restores both.
+    ///
+    /// The interrupted instruction has already taken effect, so if saving the state faults only
+    /// the partial frame is undone and the fault goes to the trap handler as if raised by the
+    /// next instruction.
+    fn interrupt(&mut self) {
+        let status = self.interrupts.enabled as i32;
+        let pc = self.getpc();
+        let frame_start = self.journal.len();
+        isa::push(self, status);
+        isa::push(self, pc);
+        if self.has_error() {
+            self.rollback_to(frame_start);
+            self.journal.clear();
+            self.trap();
+            return;
+        }
+        self.interrupts.enabled = false;
+        self.store(addrs::PC, self.interrupts.vector.unwrap());
+    }
+
+    pub fn return_from_interrupt(&mut self, pc: i32, status: i32) {
+        self.jump_to(pc);
+        if !self.has_error() {
+            self.interrupts.enabled = status & 1 != 0;
+        }
+    }
+
+    fn rollback(&mut self) {
+        self.rollback_to(0);
+    }
+
+    /// Undoes the stores made after the journal had `start` entries.
+    fn rollback_to(&mut self, start: usize) {
+        while self.journal.len() > start {
+            let (addr, val) = self.journal.pop().unwrap();
+            self.mem[addr] = val;
+        }
+    }
+
     fn fetch_inst(&self, addr: i32) -> Result<Inst, MachineError> {
         if !self.code_access_ok(addr) {
             return Err(CodeAccessSegFault { addr });
@@ -459,3 +944,156 @@ impl Debug for Machine {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::isa::OP_LIST;
+
+    /// Addresses no instruction may touch: below the address space, the code segment and
+    /// past the end of the address space.
+    const BAD_ADDRS: [i32; 3] = [-1, segs::CODE.start(), segs::ADDR_SPACE.end];
+
+    /// A machine about to run `op arg` with `stack` pushed, bottom first. `None` fills the
+    /// whole stack with `fill`.
+    fn machine_with(op: &str, arg: i32, stack: Option<&[i32]>, fill: i32) -> Machine {
+        let mut m = Machine::new();
+        m.debug_on_error = false;
+        let inst = m.encoder.make_inst(op, arg).unwrap();
+        let bin_inst = m.encoder.encode(&inst);
+        m.load_code(&[bin_inst]);
+        let stack_end = m.stack_range().end;
+        let values: Vec<i32> = match stack {
+            Some(values) => values.to_vec(),
+            None => vec![fill; (stack_end - addrs::INIT_SP) as usize],
+        };
+        for (i, val) in values.iter().enumerate() {
+            m.mem[addrs::INIT_SP + i as i32] = *val;
+        }
+        m.mem[addrs::SP] = addrs::INIT_SP + values.len() as i32;
+        m.set_status(Running);
+        m
+    }
+
+    #[test]
+    fn fault_codes_follow_the_table() {
+        assert_eq!(IllegalDirectWriteSP.fault_code(), Some(2));
+        assert_eq!(StackUnderflow { sp: 4, depth: 1 }.fault_code(), Some(9));
+        assert_eq!(CannotDecodeInst(-1).fault_code(), Some(6));
+        assert_eq!(StackOverflow { newsp: 0 }.fault_code(), Some(19));
+        assert_eq!(ProgramExit(1).fault_code(), None);
+        assert_eq!(ReplayDiverged.fault_code(), None);
+    }
+
+    #[test]
+    fn every_fault_code_has_a_variant() {
+        let trappable = [
+            IllegalSPReductionBelowMin { newsp: 0 },
+            IllegalDirectWriteSP,
+            IllegalDirectWritePC,
+            ImminentPCSegFault { newpc: 0 },
+            InvalidInstruction,
+            CannotDecodeInst(0),
+            StackAccessBeyondSP { sp: 0, addr: 0 },
+            StackAccessSegFault { addr: 0 },
+            StackUnderflow { sp: 0, depth: 0 },
+            CodeAccessSegFault { addr: 0 },
+            NoSuchEnvCall(0),
+            DivisionByZero { pc: 0 },
+            InvalidShiftAmount { pc: 0, shamt: 0 },
+            ArithmeticOverflow { pc: 0 },
+            LoadAddressOutOfBounds { addr: 0 },
+            StoreAddressOutOfBounds { addr: 0 },
+            MisalignedAccess { byte_addr: 0 },
+            AttemptedWriteToCodeSegment { addr: 0 },
+            StackOverflow { newsp: 0 },
+        ];
+        let codes: Vec<_> = trappable.iter().map(|err| err.fault_code()).collect();
+        let expected: Vec<_> = FAULT_CODES.iter().map(|&(_, code)| Some(code)).collect();
+        assert_eq!(codes, expected);
+    }
+
+    #[test]
+    fn faulting_instructions_leave_state_untouched() {
+        let mut cases: Vec<(Option<Vec<i32>>, i32, i32)> = vec![(Some(vec![]), 0, 0)];
+        for &addr in BAD_ADDRS.iter() {
+            cases.push((Some(vec![addr; 4]), 0, 0));
+            cases.push((Some(vec![0; 4]), addr, 0));
+            cases.push((None, 0, addr));
+        }
+        for op in OP_LIST.iter() {
+            // ebreak enters the debugger and never faults
+            if op.name == "ebreak" {
+                continue;
+            }
+            for (stack, arg, fill) in cases.iter() {
+                // ecall 0 is `exit`, the one call that doesn't touch the host
+                let arg = if op.name == "ecall" { 0 } else { *arg };
+                let mut m = machine_with(op.name, arg, stack.as_deref(), *fill);
+                let before = m.mem.clone();
+                let (pc, sp) = (m.getpc(), m.getsp());
+                m.cycle();
+                if !m.has_error() {
+                    continue;
+                }
+                let case = format!("{} {} with stack {:?} / {:#x}", op.name, arg, stack, fill);
+                assert_eq!(m.getpc(), pc, "pc moved: {}", case);
+                assert_eq!(m.getsp(), sp, "sp moved: {}", case);
+                assert!(m.mem == before, "memory changed: {}", case);
+            }
+        }
+    }
+
+    #[test]
+    fn indirect_jumps_past_the_address_range_fault() {
+        for &(addr, offset) in [(i32::MAX, 1), (i32::MIN, -1)].iter() {
+            for op in ["jumpr", "jalr"].iter() {
+                let mut m = machine_with(op, offset, Some(&[addr]), 0);
+                m.cycle();
+                let newpc = addr.wrapping_add(offset);
+                assert_eq!(m.status, Error(ImminentPCSegFault { newpc }), "{}", op);
+            }
+        }
+    }
+
+    #[test]
+    fn bulk_ops_fault_on_ranges_past_i32_max() {
+        let load = |addr| LoadAddressOutOfBounds { addr };
+        let store = |addr| StoreAddressOutOfBounds { addr };
+        let (heap, top) = (segs::HEAP.start(), i32::MAX - 2);
+        // stacks are bottom first, so the first argument comes last
+        let cases = [
+            ("memset", [10, 7, top], store(top)),
+            ("memset", [-1, 7, heap], store(heap)),
+            ("memcpy", [10, top, heap], load(top)),
+            ("memmove", [10, heap, top], store(top)),
+            ("memcmp", [10, top, heap], load(top)),
+            ("memcmp", [-3, heap, heap], load(heap)),
+        ];
+        for (op, stack, fault) in cases.iter() {
+            let mut m = machine_with(op, 0, Some(stack), 0);
+            m.cycle();
+            assert_eq!(m.status, Error(fault.clone()), "{} {:?}", op, stack);
+        }
+    }
+
+    #[test]
+    fn faulting_interrupt_undoes_its_frame_only() {
+        // `drop` frees one slot, the interrupt needs two
+        let mut m = machine_with("drop", 0, None, 7);
+        m.interrupts.enabled = true;
+        m.interrupts.vector = Some(segs::CODE.start());
+        m.set_timer(1);
+        let stack_end = m.stack_range().end;
+        m.cycle();
+        assert_eq!(
+            m.status,
+            Error(StackOverflow {
+                newsp: stack_end + 1
+            })
+        );
+        assert_eq!(m.getsp(), stack_end - 1);
+        assert_eq!(m.getpc(), segs::CODE.start() + 1);
+        assert_eq!(m.mem[stack_end - 1], 7);
+    }
+}
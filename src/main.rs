@@ -1,51 +1,204 @@
+use std::cell::RefCell;
 use std::error::Error;
 use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
 
 use clap::Clap;
 
 use machine::*;
 
-use crate::assembler::{assemble_file, AssemblyResult};
+use crate::assembler::{assemble_file, assemble_from_source, AssemblyResult};
+use crate::cluster::Cluster;
+use crate::environment::Rng;
+use crate::record::Recording;
+use crate::sandbox::{EcallFamily, Sandbox};
+use crate::trace::TraceOut;
+use crate::vfs::{FileSystem, HostFs, MemFs};
 
 mod assembler;
+mod cluster;
 mod encoder;
 mod environment;
 mod isa;
 mod linker;
 mod machine;
 mod mem;
+mod process;
+mod record;
+mod sandbox;
+mod threads;
+mod trace;
 mod util;
+mod vfs;
 
 #[derive(Clap)]
 #[clap(version = "1.0", author = "Mitchell Justin")]
 struct Opts {
-    filename: String,
+    /// Programs to run, machine i runs the (i mod n)th one
+    #[clap(required = true)]
+    filenames: Vec<String>,
+
+    /// Number of machines to run as a cluster, defaults to one per program
+    #[clap(long)]
+    machines: Option<usize>,
 
     #[clap(short, long)]
     debug_on_err: bool,
 
     #[clap(short, default_value = "1000000")]
     max_cycles: usize,
+
+    #[clap(long)]
+    trap_overflow: bool,
+
+    /// Let programs open TCP sockets
+    #[clap(long)]
+    allow_net: bool,
+
+    /// Directory programs see as the filesystem root, they can't open anything outside it
+    #[clap(long)]
+    root: Option<PathBuf>,
+
+    /// Paths programs may only read, can be given several times
+    #[clap(long, number_of_values = 1)]
+    read_only: Vec<PathBuf>,
+
+    /// Paths programs may read and write, can be given several times.
+    /// Without any allowlist every path is read-write
+    #[clap(long, number_of_values = 1)]
+    read_write: Vec<PathBuf>,
+
+    /// Run programs on an in-memory filesystem, preloaded from a directory or a manifest
+    /// of `<guest path> <host path>` lines
+    #[clap(long)]
+    vfs: Option<PathBuf>,
+
+    /// Directory to write the in-memory filesystem out to after the run
+    #[clap(long, requires = "vfs")]
+    vfs_dump: Option<PathBuf>,
+
+    /// Log every ecall with its arguments and result to stderr
+    #[clap(long)]
+    trace_ecalls: bool,
+
+    /// Log ecalls to this file instead of stderr
+    #[clap(long)]
+    trace_file: Option<PathBuf>,
+
+    /// Save the results of every ecall that touches the host to this file
+    #[clap(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Rerun a program against a file saved by --record, without touching the host
+    #[clap(long)]
+    replay: Option<PathBuf>,
+
+    /// Seed for the `random` ecall, so runs get the same numbers every time
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Ecall families to turn off: fs, net, process, thread, cluster, trap, time or random
+    #[clap(long, number_of_values = 1)]
+    deny: Vec<EcallFamily>,
 }
 
 fn main() {
     let opts: Opts = Opts::parse();
 
-    let mut machine = Machine::new();
-    machine.max_cycles = opts.max_cycles;
-    machine.debug_on_error = opts.debug_on_err;
+    let mut sandbox = Sandbox {
+        root: opts.root.clone(),
+        read_only: opts.read_only.clone(),
+        read_write: opts.read_write.clone(),
+        ..Default::default()
+    };
+    if opts.allow_net {
+        sandbox.disabled.remove(&EcallFamily::Net);
+    }
+    sandbox.disabled.extend(opts.deny.iter().cloned());
+
+    let memfs = opts.vfs.as_ref().map(|source| {
+        let mut memfs = MemFs::default();
+        if source.is_dir() {
+            memfs.preload_dir(source)
+        } else {
+            memfs.preload_manifest(source)
+        }
+        .unwrap();
+        Rc::new(RefCell::new(memfs))
+    });
+    let fs: Rc<RefCell<dyn FileSystem>> = match &memfs {
+        Some(memfs) => memfs.clone(),
+        None => Rc::new(RefCell::new(HostFs)),
+    };
 
-    let filename = opts.filename;
+    let trace: Option<TraceOut> = match &opts.trace_file {
+        Some(path) => Some(Rc::new(RefCell::new(Box::new(
+            fs::File::create(path).unwrap(),
+        )))),
+        None if opts.trace_ecalls => Some(Rc::new(RefCell::new(Box::new(io::stderr())))),
+        None => None,
+    };
+
+    let recording = match (&opts.record, &opts.replay) {
+        (Some(path), _) => Some(Recording::record_to(path).unwrap()),
+        (_, Some(path)) => Some(Recording::replay_from(path).unwrap()),
+        _ => None,
+    }
+    .map(|recording| Rc::new(RefCell::new(recording)));
+
+    let nmachines = opts.machines.unwrap_or(opts.filenames.len());
+    let mut machines: Vec<Machine> = (0..nmachines)
+        .map(|i| {
+            let mut machine = Machine::new();
+            machine.max_cycles = opts.max_cycles;
+            machine.debug_on_error = opts.debug_on_err;
+            machine.trap_overflow = opts.trap_overflow;
+            machine.env.sandbox = sandbox.clone();
+            machine.env.fs = fs.clone();
+            machine.env.trace = trace.clone();
+            machine.env.recording = recording.clone();
+            if let Some(seed) = opts.seed {
+                // each machine of a cluster gets its own sequence
+                machine.env.rng = Rng::new(seed.wrapping_add(i as u64));
+            }
+            load_file(
+                &mut machine,
+                opts.filenames[i % opts.filenames.len()].clone(),
+            )
+            .unwrap();
+            machine
+        })
+        .collect();
+
+    if machines.len() == 1 {
+        let mut machine = machines.pop().unwrap();
+        machine.run();
+        if !machine.debug_on_error && machine.status != MachineStatus::Stopped {
+            eprintln!("{:?}", machine);
+        }
+    } else {
+        let mut cluster = Cluster::new(machines);
+        cluster.run();
+        for (id, machine) in cluster.machines.iter().enumerate() {
+            if machine.status != MachineStatus::Stopped {
+                eprintln!("machine {}: {:?}", id, machine);
+            }
+        }
+    }
+
+    if let (Some(memfs), Some(dir)) = (&memfs, &opts.vfs_dump) {
+        memfs.borrow().dump(dir).unwrap();
+    }
+}
+
+fn load_file(machine: &mut Machine, filename: String) -> Result<(), Box<dyn Error>> {
     let extension = filename.split(".").last().unwrap_or("");
     match extension {
-        "asm" => assemble_and_load_file(&mut machine, filename),
-        "bin" => load_binary(&mut machine, filename),
-        _ => panic!("Can only read .bin or .asm files"),
-    }.unwrap();
-
-    machine.run();
-    if !machine.debug_on_error && machine.status != MachineStatus::Stopped {
-        eprintln!("{:?}", machine);
+        "asm" => assemble_and_load_file(machine, filename),
+        "bin" => load_binary(machine, filename),
+        _ => Err("Can only read .bin or .asm files".into()),
     }
 }
 
@@ -70,6 +223,33 @@ fn assemble_and_load_file(machine: &mut Machine, filename: String) -> Result<(),
     Ok(())
 }
 
+/// Loads a program that has already been read in, without writing the .bin and .expanded.asm
+/// files `load_file` leaves next to it. Programs started by `spawn` are loaded this way.
+fn load_program(machine: &mut Machine, filename: &str, data: &[u8]) -> Result<(), Box<dyn Error>> {
+    let extension = filename.split(".").last().unwrap_or("");
+    match extension {
+        "asm" => {
+            let AssemblyResult {
+                binary, debug_info, ..
+            } = assemble_from_source(data)?;
+            machine.debug_info = debug_info;
+            machine.load_code(&binary);
+        }
+        "bin" => {
+            let words = data.chunks_exact(4);
+            if !words.remainder().is_empty() {
+                return Err(format!("{} isn't a whole number of words", filename).into());
+            }
+            let words: Vec<i32> = words
+                .map(|word| i32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                .collect();
+            machine.load_code(&words);
+        }
+        _ => return Err("Can only read .bin or .asm files".into()),
+    }
+    Ok(())
+}
+
 fn load_binary(machine: &mut Machine, filename: String) -> Result<(), Box<dyn Error>> {
     let binary = fs::read(filename)?;
     let (_, bin_i32, _) = unsafe { binary.align_to::<i32>() };
@@ -64,6 +64,7 @@ pub fn inst_loc_to_addr(loc: usize) -> i32 {
     loc as i32 + addrs::CODE_ENTRY
 }
 
+#[derive(Clone, PartialEq)]
 pub struct Memory {
     vec: Vec<i32>,
 }
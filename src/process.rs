@@ -0,0 +1,208 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+
+use crate::environment::RetCode;
+use crate::machine::MachineStatus::*;
+use crate::machine::{Machine, MachineError};
+use crate::sandbox::Access;
+use crate::vfs::VfsFile;
+
+/// Bytes a pipe buffers before writers have to wait.
+pub const PIPE_CAPACITY: usize = 4096;
+
+struct Pipe {
+    buf: VecDeque<u8>,
+    readers: usize,
+    writers: usize,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum PipeEndKind {
+    Read,
+    Write,
+}
+
+/// One end of a pipe. The pipe is closed for reading or writing once every
+/// end of that kind has been dropped.
+pub struct PipeEnd {
+    pipe: Rc<RefCell<Pipe>>,
+    kind: PipeEndKind,
+}
+
+impl PipeEnd {
+    fn new(pipe: Rc<RefCell<Pipe>>, kind: PipeEndKind) -> PipeEnd {
+        {
+            let mut p = pipe.borrow_mut();
+            match kind {
+                PipeEndKind::Read => p.readers += 1,
+                PipeEndKind::Write => p.writers += 1,
+            }
+        }
+        PipeEnd { pipe, kind }
+    }
+}
+
+impl Clone for PipeEnd {
+    fn clone(&self) -> Self {
+        PipeEnd::new(self.pipe.clone(), self.kind)
+    }
+}
+
+impl Drop for PipeEnd {
+    fn drop(&mut self) {
+        let mut p = self.pipe.borrow_mut();
+        match self.kind {
+            PipeEndKind::Read => p.readers -= 1,
+            PipeEndKind::Write => p.writers -= 1,
+        }
+    }
+}
+
+/// Returns the (read, write) ends of a new pipe.
+pub fn pipe() -> (PipeEnd, PipeEnd) {
+    let pipe = Rc::new(RefCell::new(Pipe {
+        buf: VecDeque::new(),
+        readers: 0,
+        writers: 0,
+    }));
+    (
+        PipeEnd::new(pipe.clone(), PipeEndKind::Read),
+        PipeEnd::new(pipe, PipeEndKind::Write),
+    )
+}
+
+/// Something a file descriptor refers to.
+pub enum Handle {
+    /// A file with the access the sandbox gave when it was opened
+    File(Box<dyn VfsFile>, Access),
+    Pipe(PipeEnd),
+    Listener(TcpListener),
+    Stream(TcpStream),
+    /// The host's stderr, for a child whose stdout is its parent's fd 2
+    Stderr,
+}
+
+impl Handle {
+    pub fn try_clone(&self) -> io::Result<Handle> {
+        match self {
+            Handle::File(file, access) => Ok(Handle::File(file.try_clone()?, *access)),
+            Handle::Pipe(end) => Ok(Handle::Pipe(end.clone())),
+            Handle::Listener(listener) => Ok(Handle::Listener(listener.try_clone()?)),
+            Handle::Stream(stream) => Ok(Handle::Stream(stream.try_clone()?)),
+            Handle::Stderr => Ok(Handle::Stderr),
+        }
+    }
+
+    /// Whether waiting on this handle means waiting on the host rather than on the VM.
+    pub fn is_socket(&self) -> bool {
+        matches!(self, Handle::Listener(_) | Handle::Stream(_))
+    }
+
+    /// Writes some of `data`, or returns None if the caller has to wait for room in a pipe
+    /// or a socket's send buffer.
+    pub fn write(&mut self, data: &[u8]) -> Option<io::Result<usize>> {
+        match self {
+            Handle::File(_, Access::ReadOnly) => Some(Err(io::ErrorKind::PermissionDenied.into())),
+            Handle::File(file, Access::ReadWrite) => Some(file.write(data)),
+            Handle::Stream(stream) => would_block(stream.write(data)),
+            Handle::Listener(_) => Some(Err(io::ErrorKind::NotConnected.into())),
+            Handle::Stderr => {
+                let mut stderr = io::stderr();
+                Some(stderr.write(data).and_then(|n| stderr.flush().map(|_| n)))
+            }
+            Handle::Pipe(end) => {
+                let mut p = end.pipe.borrow_mut();
+                if end.kind != PipeEndKind::Write || p.readers == 0 {
+                    return Some(Err(io::ErrorKind::BrokenPipe.into()));
+                }
+                let n = data.len().min(PIPE_CAPACITY - p.buf.len());
+                if n == 0 && !data.is_empty() {
+                    return None;
+                }
+                p.buf.extend(&data[..n]);
+                Some(Ok(n))
+            }
+        }
+    }
+
+    /// Reads into `data`, or returns None if the caller has to wait for a pipe to be written to
+    /// or for data on a socket. An empty pipe without writers reads as end of file.
+    pub fn read(&mut self, data: &mut [u8]) -> Option<io::Result<usize>> {
+        match self {
+            Handle::File(file, _) => Some(file.read(data)),
+            Handle::Stream(stream) => would_block(stream.read(data)),
+            Handle::Listener(_) => Some(Err(io::ErrorKind::NotConnected.into())),
+            Handle::Stderr => Some(Err(io::ErrorKind::PermissionDenied.into())),
+            Handle::Pipe(end) => {
+                let mut p = end.pipe.borrow_mut();
+                if end.kind != PipeEndKind::Read {
+                    return Some(Err(io::ErrorKind::PermissionDenied.into()));
+                }
+                if p.buf.is_empty() && p.writers > 0 && !data.is_empty() {
+                    return None;
+                }
+                let n = data.len().min(p.buf.len());
+                for (dst, src) in data.iter_mut().zip(p.buf.drain(..n)) {
+                    *dst = src;
+                }
+                Some(Ok(n))
+            }
+        }
+    }
+}
+
+/// Sockets are nonblocking, so the VM can run something else while one isn't ready.
+/// None if the operation has to wait.
+pub fn would_block<T>(result: io::Result<T>) -> Option<io::Result<T>> {
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => None,
+        result => Some(result),
+    }
+}
+
+pub enum ChildState {
+    Running(Box<Machine>),
+    Exited(i32),
+}
+
+/// A program started by `spawn`, run alongside its parent one cycle at a time.
+pub struct Child {
+    pub pid: i32,
+    pub state: ChildState,
+}
+
+impl Child {
+    /// Runs the child for one cycle. Returns whether it got anywhere.
+    pub fn step(&mut self) -> bool {
+        let machine = match &mut self.state {
+            ChildState::Running(machine) => machine,
+            ChildState::Exited(_) => return false,
+        };
+        if machine.status == Blocked {
+            // retry whatever it was waiting on
+            machine.set_status(Running);
+        }
+        machine.cycle();
+        match &machine.status {
+            Running | Debugging => true,
+            Blocked => false,
+            status => {
+                let code = match status {
+                    Stopped => 0,
+                    Error(MachineError::ProgramExit(code)) => *code,
+                    _ => RetCode::ChildFaulted as i32,
+                };
+                if *status != Stopped {
+                    eprintln!("child {}: {:?}", self.pid, machine);
+                }
+                // dropping the machine closes its end of any pipes
+                self.state = ChildState::Exited(code);
+                true
+            }
+        }
+    }
+}
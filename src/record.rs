@@ -0,0 +1,314 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::isa::pop;
+use crate::machine::{Machine, MachineError};
+
+/// One ecall's outcome: its return value and the memory it wrote, or that it had to wait.
+pub struct Entry {
+    name: String,
+    retval: Option<i32>,
+    stores: Vec<(i32, i32)>,
+    /// Times in a row the ecall had to wait, for entries without a result
+    waits: usize,
+}
+
+/// Log lines look like `read 5 13=104 14=101 ...`, an ecall that had to wait 3 times in a row
+/// is `read blocked 3`.
+impl Entry {
+    fn to_line(&self) -> String {
+        let mut line = match self.retval {
+            Some(retval) => format!("{} {}", self.name, retval),
+            None => format!("{} blocked {}", self.name, self.waits),
+        };
+        for (addr, val) in self.stores.iter() {
+            line += &format!(" {}={}", addr, val);
+        }
+        line
+    }
+
+    fn parse(line: &str) -> Option<Entry> {
+        let mut parts = line.split_whitespace();
+        let name = parts.next()?.to_string();
+        let (retval, waits) = match parts.next()? {
+            "blocked" => match parts.next()?.parse().ok()? {
+                0 => return None,
+                waits => (None, waits),
+            },
+            retval => (Some(retval.parse().ok()?), 0),
+        };
+        let stores = parts
+            .map(|store| {
+                let (addr, val) = store.split_at(store.find('=')?);
+                Some((addr.parse().ok()?, val[1..].parse().ok()?))
+            })
+            .collect::<Option<_>>()?;
+        Some(Entry {
+            name,
+            retval,
+            stores,
+            waits,
+        })
+    }
+}
+
+pub enum Recording {
+    /// A wait is held back until the ecall stops waiting, so polling a socket takes one line
+    Record(File, Option<Entry>),
+    Replay(VecDeque<Entry>),
+}
+
+/// Shared by every machine of a run, so the log follows the order the ecalls happened in.
+pub type RecordLog = Rc<RefCell<Recording>>;
+
+impl Recording {
+    pub fn record_to(path: &Path) -> io::Result<Recording> {
+        Ok(Recording::Record(File::create(path)?, None))
+    }
+
+    pub fn replay_from(path: &Path) -> io::Result<Recording> {
+        let mut entries = VecDeque::new();
+        for (line_no, line) in fs::read_to_string(path)?.lines().enumerate() {
+            match Entry::parse(line) {
+                Some(entry) => entries.push_back(entry),
+                None => {
+                    let msg = format!("bad recording at line {}: {}", line_no + 1, line);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                }
+            }
+        }
+        Ok(Recording::Replay(entries))
+    }
+}
+
+/// Whether an ecall talks to the host, so its results are recorded and replayed
+/// rather than depending on the outside world.
+pub fn is_recorded(name: &str) -> bool {
+    matches!(
+        name,
+        "open"
+            | "close"
+            | "read"
+            | "write"
+            | "read_packed"
+            | "write_packed"
+            | "seek"
+            | "socket_listen"
+            | "accept"
+            | "connect"
+            | "send"
+            | "recv"
+            | "time_ms"
+            | "monotonic_ns"
+            | "sleep_ms"
+            | "random"
+            | "printf"
+            | "printf_packed"
+    )
+}
+
+/// Pops the ecall's arguments and repeats what it did in the recording, instead of running it.
+/// Returns None if the run isn't a replay.
+pub fn replay(m: &mut Machine, name: &str, nargs: usize) -> Option<i32> {
+    let log = m.env.recording.clone()?;
+    let mut log = log.borrow_mut();
+    let entries = match &mut *log {
+        Recording::Replay(entries) => entries,
+        Recording::Record(..) => return None,
+    };
+    for _ in 0..nargs {
+        pop(m);
+    }
+    if m.has_error() {
+        return Some(0);
+    }
+    let entry = match entries.front_mut() {
+        Some(entry) if entry.name == name => entry,
+        _ => {
+            m.set_error(MachineError::ReplayDiverged);
+            return Some(0);
+        }
+    };
+    if entry.retval.is_none() {
+        entry.waits -= 1;
+        if entry.waits == 0 {
+            entries.pop_front();
+        }
+        // the recording has what the ecall got next, so this never deadlocks
+        m.block_on_host();
+        return Some(0);
+    }
+    let entry = entries.pop_front().unwrap();
+    let retval = entry.retval.unwrap();
+    for (addr, val) in entry.stores {
+        m.store(addr, val);
+    }
+    Some(retval)
+}
+
+/// The memory an ecall changed, once per address.
+fn new_values(m: &Machine, journal_start: usize) -> Vec<(i32, i32)> {
+    let mut stores: Vec<(i32, i32)> = Vec::new();
+    for (addr, val) in m.stores_since(journal_start) {
+        // sp is kept too, so replaying printf pops its format arguments as well
+        if !stores.iter().any(|&(seen, _)| seen == addr) {
+            stores.push((addr, val));
+        }
+    }
+    stores
+}
+
+/// Logs an ecall that just ran, given the journal length from before it ran.
+pub fn record(m: &Machine, name: &str, retval: i32, journal_start: usize) {
+    let log = match &m.env.recording {
+        Some(log) => log.clone(),
+        None => return,
+    };
+    let mut log = log.borrow_mut();
+    let (file, waiting) = match &mut *log {
+        Recording::Record(file, waiting) => (file, waiting),
+        Recording::Replay(_) => return,
+    };
+    if m.has_error() {
+        return;
+    }
+    if m.is_blocking() {
+        match waiting {
+            Some(wait) if wait.name == name => wait.waits += 1,
+            _ => {
+                if let Some(wait) = waiting.take() {
+                    writeln!(file, "{}", wait.to_line()).unwrap();
+                }
+                *waiting = Some(Entry {
+                    name: name.to_string(),
+                    retval: None,
+                    stores: Vec::new(),
+                    waits: 1,
+                });
+            }
+        }
+        return;
+    }
+    if let Some(wait) = waiting.take() {
+        writeln!(file, "{}", wait.to_line()).unwrap();
+    }
+    let entry = Entry {
+        name: name.to_string(),
+        retval: Some(retval),
+        stores: new_values(m, journal_start),
+        waits: 0,
+    };
+    writeln!(file, "{}", entry.to_line()).unwrap();
+}
+
+impl Drop for Recording {
+    /// Writes out a wait the run ended in.
+    fn drop(&mut self) {
+        if let Recording::Record(file, Some(wait)) = self {
+            let _ = writeln!(file, "{}", wait.to_line());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::CALL_LIST;
+    use crate::isa::{ecall, push};
+
+    fn replaying(lines: &[&str]) -> Machine {
+        let entries = lines
+            .iter()
+            .map(|line| Entry::parse(line).unwrap())
+            .collect();
+        let mut m = Machine::new();
+        m.env.recording = Some(Rc::new(RefCell::new(Recording::Replay(entries))));
+        m
+    }
+
+    fn call(m: &mut Machine, name: &str, args: &[i32]) -> i32 {
+        for &arg in args.iter().rev() {
+            push(m, arg);
+        }
+        let callcode = CALL_LIST.iter().position(|(_, n, _)| *n == name).unwrap();
+        ecall(m, callcode as i32);
+        pop(m).unwrap()
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let entry = Entry::parse("read 2 13=104 14=-1").unwrap();
+        assert_eq!(entry.name, "read");
+        assert_eq!(entry.retval, Some(2));
+        assert_eq!(entry.stores, vec![(13, 104), (14, -1)]);
+        assert_eq!(entry.to_line(), "read 2 13=104 14=-1");
+
+        let entry = Entry::parse("accept blocked 3").unwrap();
+        assert_eq!(entry.retval, None);
+        assert_eq!(entry.waits, 3);
+        assert!(entry.stores.is_empty());
+        assert_eq!(entry.to_line(), "accept blocked 3");
+    }
+
+    #[test]
+    fn malformed_entries_are_rejected() {
+        for line in [
+            "",
+            "read",
+            "read x",
+            "read 1 13",
+            "read 1 a=1",
+            "read 1 13=b",
+            "read blocked",
+            "read blocked 0",
+        ]
+        .iter()
+        {
+            assert!(Entry::parse(line).is_none(), "{:?} parsed", line);
+        }
+    }
+
+    #[test]
+    fn bad_recordings_name_the_line() {
+        let path = std::env::temp_dir().join(format!("nais-replay-{}", std::process::id()));
+        fs::write(&path, "open 3\nread five\n").unwrap();
+        let result = Recording::replay_from(&path);
+        let _ = fs::remove_file(&path);
+        let err = result.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 2"), "{}", err);
+    }
+
+    #[test]
+    fn replay_repeats_results_and_stores() {
+        let mut m = replaying(&["read 2 100=104 101=105"]);
+        assert_eq!(call(&mut m, "read", &[1, 100, 10]), 2);
+        assert_eq!((m.load(100), m.load(101)), (104, 105));
+        assert!(!m.has_error());
+    }
+
+    #[test]
+    fn replay_blocks_where_the_recording_did() {
+        let mut m = replaying(&["read blocked 2", "read 0"]);
+        for _ in 0..2 {
+            call(&mut m, "read", &[3, 100, 10]);
+            assert!(m.is_blocking() && m.is_waiting_on_host());
+            m.threads.request = None;
+        }
+        assert_eq!(call(&mut m, "read", &[3, 100, 10]), 0);
+        assert!(!m.is_blocking() && !m.has_error());
+    }
+
+    #[test]
+    fn replay_faults_when_the_program_diverges() {
+        let mut m = replaying(&["read 2"]);
+        call(&mut m, "write", &[1, 100, 2]);
+        assert!(m.has_error());
+    }
+}
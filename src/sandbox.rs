@@ -0,0 +1,325 @@
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+/// Groups of ecalls that can be turned off together.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum EcallFamily {
+    Fs,
+    Net,
+    Process,
+    Thread,
+    Cluster,
+    Trap,
+    Time,
+    Random,
+}
+
+impl EcallFamily {
+    /// The family an ecall belongs to, None for the basics that are always allowed.
+    pub fn of_call(name: &str) -> Option<EcallFamily> {
+        use EcallFamily::*;
+        let family = match name {
+            "open" | "seek" => Fs,
+            "socket_listen" | "accept" | "connect" | "send" | "recv" => Net,
+            "spawn" | "wait" | "pipe" => Process,
+            "thread_spawn" | "thread_yield" | "thread_exit" | "thread_join" | "chan_new"
+            | "chan_send" | "chan_recv" => Thread,
+            "machine_id" | "msg_send" | "msg_recv" => Cluster,
+            "set_trap_handler" | "set_timer" | "set_interrupt_vector" => Trap,
+            "time_ms" | "monotonic_ns" | "sleep_ms" => Time,
+            "random" => Random,
+            _ => return None,
+        };
+        Some(family)
+    }
+}
+
+impl FromStr for EcallFamily {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use EcallFamily::*;
+        match s {
+            "fs" => Ok(Fs),
+            "net" => Ok(Net),
+            "process" => Ok(Process),
+            "thread" => Ok(Thread),
+            "cluster" => Ok(Cluster),
+            "trap" => Ok(Trap),
+            "time" => Ok(Time),
+            "random" => Ok(Random),
+            _ => Err(format!("unknown ecall family: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Access {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// What a program may touch. Host paths the program uses are relative to `root`
+/// when one is set and can't lead out of it.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    pub root: Option<PathBuf>,
+    /// Paths that may only be read. If both allowlists are empty, every path is read-write.
+    pub read_only: Vec<PathBuf>,
+    pub read_write: Vec<PathBuf>,
+    pub disabled: HashSet<EcallFamily>,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Sandbox {
+            root: None,
+            read_only: Vec::new(),
+            read_write: Vec::new(),
+            // the network has to be asked for explicitly
+            disabled: [EcallFamily::Net].iter().cloned().collect(),
+        }
+    }
+}
+
+impl Sandbox {
+    pub fn allows_call(&self, name: &str) -> bool {
+        match EcallFamily::of_call(name) {
+            Some(family) => !self.disabled.contains(&family),
+            None => true,
+        }
+    }
+
+    /// Maps a path from the program to a path on its filesystem and the access the program
+    /// has to it, or None if it may not use the path at all. Off the host, every path is
+    /// treated as if it were in a jail.
+    pub fn resolve(&self, path: &str, on_host: bool) -> Option<(PathBuf, Access)> {
+        let jailed = self.root.is_some() || !on_host;
+        let path = normalize(Path::new(path), jailed)?;
+        let access = self.access(&path, jailed)?;
+        let root = match &self.root {
+            Some(root) if on_host => root,
+            _ => return Some((path, access)),
+        };
+        let host_path = root.join(&path);
+        // symlinks inside the jail mustn't lead out of it either
+        let root = root.canonicalize().ok()?;
+        let existing = host_path.ancestors().find(|p| p.exists())?;
+        if !existing.canonicalize().ok()?.starts_with(&root) {
+            return None;
+        }
+        Some((host_path, access))
+    }
+
+    fn access(&self, path: &Path, jailed: bool) -> Option<Access> {
+        if self.read_only.is_empty() && self.read_write.is_empty() {
+            return Some(Access::ReadWrite);
+        }
+        let listed = |list: &[PathBuf]| {
+            list.iter().any(|allowed| match normalize(allowed, jailed) {
+                Some(allowed) => path.starts_with(allowed),
+                None => false,
+            })
+        };
+        if listed(&self.read_write) {
+            Some(Access::ReadWrite)
+        } else if listed(&self.read_only) {
+            Some(Access::ReadOnly)
+        } else {
+            None
+        }
+    }
+}
+
+/// Resolves `.` and `..` without looking at the filesystem. Inside a jail absolute paths
+/// start at the jail's root and `..` can't go above it.
+fn normalize(path: &Path, jailed: bool) -> Option<PathBuf> {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir if jailed => normal.clear(),
+            Component::Prefix(_) | Component::RootDir => normal.push(component),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                let at_top = matches!(
+                    normal.components().next_back(),
+                    None | Some(Component::ParentDir)
+                );
+                if at_top && jailed {
+                    return None;
+                }
+                if at_top {
+                    normal.push("..");
+                } else {
+                    normal.pop();
+                }
+            }
+            Component::Normal(name) => normal.push(name),
+        }
+    }
+    Some(normal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn norm(path: &str, jailed: bool) -> Option<String> {
+        normalize(Path::new(path), jailed).map(|p| p.to_str().unwrap().to_string())
+    }
+
+    fn allowing(read_only: &[&str], read_write: &[&str]) -> Sandbox {
+        Sandbox {
+            read_only: read_only.iter().map(PathBuf::from).collect(),
+            read_write: read_write.iter().map(PathBuf::from).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// An empty directory to jail a program in, removed again on drop.
+    struct Jail(PathBuf);
+
+    impl Jail {
+        fn new(name: &str) -> Jail {
+            let dir = std::env::temp_dir().join(format!("nais-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Jail(dir.canonicalize().unwrap())
+        }
+    }
+
+    impl Drop for Jail {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn normalize_resolves_dots() {
+        assert_eq!(norm("a/./b/../c", false).unwrap(), "a/c");
+        assert_eq!(norm("/a/../../b", false).unwrap(), "/b");
+        assert_eq!(norm("../a", false).unwrap(), "../a");
+        assert_eq!(norm("a/../../b", false).unwrap(), "../b");
+    }
+
+    #[test]
+    fn normalize_keeps_jailed_paths_inside() {
+        assert_eq!(norm("/etc/passwd", true).unwrap(), "etc/passwd");
+        assert_eq!(norm("a/../b", true).unwrap(), "b");
+        assert_eq!(norm("..", true), None);
+        assert_eq!(norm("a/../../b", true), None);
+        assert_eq!(norm("/../etc", true), None);
+    }
+
+    #[test]
+    fn everything_is_read_write_without_allowlists() {
+        let sandbox = Sandbox::default();
+        assert_eq!(
+            sandbox.resolve("/etc/passwd", true),
+            Some((PathBuf::from("/etc/passwd"), Access::ReadWrite))
+        );
+    }
+
+    #[test]
+    fn allowlists_decide_access() {
+        let sandbox = allowing(&["/data"], &["/data/out", "/tmp"]);
+        assert_eq!(
+            sandbox.resolve("/data/in.txt", true),
+            Some((PathBuf::from("/data/in.txt"), Access::ReadOnly))
+        );
+        assert_eq!(
+            sandbox.resolve("/data/out/result.txt", true),
+            Some((PathBuf::from("/data/out/result.txt"), Access::ReadWrite))
+        );
+        assert_eq!(
+            sandbox.resolve("/tmp/x", true),
+            Some((PathBuf::from("/tmp/x"), Access::ReadWrite))
+        );
+        assert_eq!(sandbox.resolve("/etc/passwd", true), None);
+        assert_eq!(sandbox.resolve("/data/../etc/passwd", true), None);
+    }
+
+    #[test]
+    fn allowlists_match_whole_components() {
+        let sandbox = allowing(&["/root"], &[]);
+        assert!(sandbox.resolve("/root/file", true).is_some());
+        assert_eq!(sandbox.resolve("/rootx", true), None);
+        assert_eq!(sandbox.resolve("/rootx/file", true), None);
+    }
+
+    #[test]
+    fn jail_maps_paths_under_its_root() {
+        let jail = Jail::new("jail");
+        let sandbox = Sandbox {
+            root: Some(jail.0.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            sandbox.resolve("/etc/passwd", true),
+            Some((jail.0.join("etc/passwd"), Access::ReadWrite))
+        );
+        assert_eq!(
+            sandbox.resolve("a/../b", true),
+            Some((jail.0.join("b"), Access::ReadWrite))
+        );
+        assert_eq!(sandbox.resolve("../etc/passwd", true), None);
+        assert_eq!(sandbox.resolve("/a/../../etc/passwd", true), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn jail_symlinks_cant_lead_out() {
+        let jail = Jail::new("symlink");
+        std::os::unix::fs::symlink("/", jail.0.join("escape")).unwrap();
+        let sandbox = Sandbox {
+            root: Some(jail.0.clone()),
+            ..Default::default()
+        };
+        assert_eq!(sandbox.resolve("escape/etc/passwd", true), None);
+    }
+
+    #[test]
+    fn jail_allowlists_are_inside_the_jail() {
+        let jail = Jail::new("allow");
+        let sandbox = Sandbox {
+            root: Some(jail.0.clone()),
+            ..allowing(&["/in"], &["/out"])
+        };
+        assert_eq!(
+            sandbox.resolve("/in/a", true),
+            Some((jail.0.join("in/a"), Access::ReadOnly))
+        );
+        assert_eq!(
+            sandbox.resolve("out/b", true),
+            Some((jail.0.join("out/b"), Access::ReadWrite))
+        );
+        assert_eq!(sandbox.resolve("/inx", true), None);
+    }
+
+    #[test]
+    fn paths_off_the_host_are_jailed() {
+        let sandbox = Sandbox::default();
+        assert_eq!(
+            sandbox.resolve("/a/../b", false),
+            Some((PathBuf::from("b"), Access::ReadWrite))
+        );
+        assert_eq!(sandbox.resolve("../etc/passwd", false), None);
+        let sandbox = allowing(&["/data"], &[]);
+        assert_eq!(
+            sandbox.resolve("data/in.txt", false),
+            Some((PathBuf::from("data/in.txt"), Access::ReadOnly))
+        );
+        assert_eq!(sandbox.resolve("/datax", false), None);
+    }
+
+    #[test]
+    fn families_can_be_disabled() {
+        let mut sandbox = Sandbox::default();
+        assert!(!sandbox.allows_call("connect"));
+        assert!(sandbox.allows_call("open"));
+        assert!(sandbox.allows_call("write"));
+        sandbox.disabled.insert(EcallFamily::Fs);
+        assert!(!sandbox.allows_call("open"));
+        assert!(sandbox.allows_call("write"));
+    }
+}
@@ -0,0 +1,101 @@
+use std::ops::Range;
+
+use crate::mem::{addrs, segs};
+
+/// Spawned threads get fixed-size stacks from the upper half of the stack segment,
+/// the main thread keeps the lower half once there are other threads.
+pub const THREAD_STACKS: Range<i32> = 0x8000..segs::STACK.end();
+pub const THREAD_STACK_SIZE: i32 = 0x1000;
+pub const MAIN_THREAD: usize = 0;
+/// Return address a spawned thread starts with, `ret` to it ends the thread.
+pub const THREAD_RETURN: i32 = 0;
+
+/// Cycles a thread may run before the scheduler switches to the next one.
+pub const QUANTUM: usize = 100;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ThreadState {
+    Runnable,
+    Finished(i32),
+}
+
+#[derive(Debug, Clone)]
+pub struct Thread {
+    pub id: usize,
+    pub state: ThreadState,
+    pub pc: i32,
+    pub sp: i32,
+    pub fp: i32,
+    pub stack: Range<i32>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SchedRequest {
+    /// Switch to the next thread once the current instruction is done.
+    Yield,
+    /// Undo the current instruction and retry it the next time the thread is scheduled.
+    Block,
+}
+
+pub struct Threads {
+    pub list: Vec<Thread>,
+    pub current: usize,
+    pub request: Option<SchedRequest>,
+    /// Threads that blocked in a row without any thread making progress
+    pub blocked_streak: usize,
+    /// Whether one of them is waiting on the host, which can't deadlock
+    pub waiting_on_host: bool,
+    pub slice_used: usize,
+}
+
+impl Default for Threads {
+    fn default() -> Self {
+        Threads {
+            list: vec![Thread {
+                id: MAIN_THREAD,
+                state: ThreadState::Runnable,
+                pc: addrs::INIT_PC,
+                sp: addrs::INIT_SP,
+                fp: addrs::INIT_FP,
+                stack: addrs::INIT_SP..segs::STACK.end(),
+            }],
+            current: MAIN_THREAD,
+            request: None,
+            blocked_streak: 0,
+            waiting_on_host: false,
+            slice_used: 0,
+        }
+    }
+}
+
+impl Threads {
+    pub fn num_runnable(&self) -> usize {
+        self.list
+            .iter()
+            .filter(|t| t.state == ThreadState::Runnable)
+            .count()
+    }
+
+    /// The next runnable thread after the current one, in round-robin order.
+    pub fn next_runnable(&self) -> Option<usize> {
+        let n = self.list.len();
+        (1..=n)
+            .map(|i| (self.current + i) % n)
+            .find(|&id| self.list[id].state == ThreadState::Runnable)
+    }
+
+    /// A stack region that isn't used by any live spawned thread.
+    pub fn free_stack(&self) -> Option<Range<i32>> {
+        let mut start = THREAD_STACKS.start;
+        while start < THREAD_STACKS.end {
+            let in_use = self.list.iter().any(|t| {
+                t.id != MAIN_THREAD && t.state == ThreadState::Runnable && t.stack.start == start
+            });
+            if !in_use {
+                return Some(start..start + THREAD_STACK_SIZE);
+            }
+            start += THREAD_STACK_SIZE;
+        }
+        None
+    }
+}
@@ -0,0 +1,261 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::environment::RetCode;
+use crate::machine::Machine;
+
+/// Where `--trace-ecalls` writes to, shared by every machine of a run.
+pub type TraceOut = Rc<RefCell<Box<dyn Write>>>;
+
+/// Longest buffer shown in full, longer ones are cut off.
+const MAX_SHOWN: i32 = 64;
+
+#[derive(Clone, Copy)]
+enum Arg {
+    Int(&'static str),
+    Fd,
+    Addr(&'static str),
+    /// A (ptr, len) pair of one char per word, read by the call
+    Buf(&'static str),
+    /// A (ptr, len) pair of one char per word, filled in by the call
+    OutBuf,
+    /// A (byte ptr, nbytes) pair, read by the call
+    Packed,
+    /// A (byte ptr, nbytes) pair, filled in by the call
+    OutPacked,
+}
+
+fn call_args(name: &str) -> &'static [Arg] {
+    use Arg::*;
+    match name {
+        "exit" => &[Int("code")],
+        "open" => &[Buf("path")],
+        "write" | "send" => &[Fd, Buf("buf")],
+        "read" | "recv" => &[Fd, OutBuf],
+        "malloc" => &[Int("size")],
+        "write_packed" => &[Fd, Packed],
+        "read_packed" => &[Fd, OutPacked],
+        "set_trap_handler" => &[Addr("handler")],
+        "set_timer" => &[Int("interval")],
+        "set_interrupt_vector" => &[Addr("vector")],
+        "thread_spawn" => &[Addr("entry"), Int("arg")],
+        "thread_exit" => &[Int("retval")],
+        "thread_join" => &[Int("thread")],
+        "chan_new" => &[Int("capacity")],
+        "chan_send" => &[Int("chan"), Int("val")],
+        "chan_recv" => &[Int("chan")],
+        "msg_send" => &[Int("dest"), Int("val")],
+        "argv" => &[Int("i"), OutBuf],
+        "pipe" => &[Addr("fds")],
+        "close" | "accept" => &[Fd],
+        "spawn" => &[Buf("path"), Addr("argv"), Int("argc"), Fd, Fd],
+        "wait" => &[Int("pid")],
+        "socket_listen" | "connect" => &[Buf("addr")],
+        "seek" => &[Fd, Int("offset"), Int("whence")],
+        "time_ms" | "monotonic_ns" => &[Addr("dst")],
+        "sleep_ms" => &[Int("ms")],
+        "printf" => &[Fd, Buf("fmt")],
+        "printf_packed" => &[Fd, Packed],
+        _ => &[],
+    }
+}
+
+fn ret_code_name(code: i32) -> Option<&'static str> {
+    use RetCode::*;
+    let codes = [
+        (PermissionDenied as i32, "PermissionDenied"),
+        (ChildFaulted as i32, "ChildFaulted"),
+        (NotInCluster as i32, "NotInCluster"),
+        (OutOfResources as i32, "OutOfResources"),
+        (UTF8Error as i32, "UTF8Error"),
+        (GenericIOError as i32, "GenericIOError"),
+        (InvalidFileDescriptor as i32, "InvalidFileDescriptor"),
+        (AddressOutOfBounds as i32, "AddressOutOfBounds"),
+        (ArgsInvalid as i32, "ArgsInvalid"),
+    ];
+    codes
+        .iter()
+        .find(|(ret, _)| *ret == code)
+        .map(|(_, name)| *name)
+}
+
+/// The arguments an ecall is about to pop, top of the stack first.
+pub fn peek_args(m: &Machine, nargs: usize) -> Vec<Option<i32>> {
+    let sp = m.getsp();
+    let stack = m.stack_range();
+    (1..=nargs as i32)
+        .map(|i| match sp - i {
+            addr if addr >= stack.start => m.peek(addr),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Logs an ecall once it has returned, so buffers it filled in can be shown.
+pub fn log_call(m: &Machine, name: &str, args: &[Option<i32>], retval: i32) {
+    let out = match &m.env.trace {
+        Some(out) => out.clone(),
+        None => return,
+    };
+    let mut shown = Vec::new();
+    // (ptr, packed) of a buffer the call fills in
+    let mut filled = None;
+    let mut raw = args.iter().cloned();
+    for arg in call_args(name) {
+        let desc = match *arg {
+            Arg::Int(label) => format!("{}={}", label, show_word(raw.next().flatten())),
+            Arg::Addr(label) => format!("{}={}", label, show_addr(raw.next().flatten())),
+            Arg::Fd => format!("fd={}", show_word(raw.next().flatten())),
+            Arg::Buf(label) => {
+                let (ptr, len) = (raw.next().flatten(), raw.next().flatten());
+                format!(
+                    "{}={}, len={}",
+                    label,
+                    show_words(m, ptr, len),
+                    show_word(len)
+                )
+            }
+            Arg::OutBuf => {
+                let (ptr, len) = (raw.next().flatten(), raw.next().flatten());
+                filled = Some((ptr, false));
+                format!("buf={}, len={}", show_addr(ptr), show_word(len))
+            }
+            Arg::Packed => {
+                let (ptr, len) = (raw.next().flatten(), raw.next().flatten());
+                format!("buf={}, len={}", show_bytes(m, ptr, len), show_word(len))
+            }
+            Arg::OutPacked => {
+                let (ptr, len) = (raw.next().flatten(), raw.next().flatten());
+                filled = Some((ptr, true));
+                format!("buf={}, len={}", show_addr(ptr), show_word(len))
+            }
+        };
+        shown.push(desc);
+    }
+    shown.extend(raw.map(show_word));
+
+    let result = if m.has_error() {
+        format!("fault {:?}", m.status)
+    } else if m.is_blocking() {
+        "blocked".to_string()
+    } else {
+        let mut result = match ret_code_name(retval) {
+            Some(code) => format!("{} ({})", retval, code),
+            None => retval.to_string(),
+        };
+        // show what a read call filled in
+        match filled {
+            Some((ptr, true)) if retval > 0 => {
+                result = format!("{} {}", result, show_bytes(m, ptr, Some(retval)))
+            }
+            Some((ptr, false)) if retval > 0 => {
+                result = format!("{} {}", result, show_words(m, ptr, Some(retval)))
+            }
+            _ => {}
+        }
+        result
+    };
+
+    let pc = m.getpc();
+    let frame = m
+        .debug_info
+        .frame_for_inst_addr
+        .get(&pc)
+        .map(String::as_str)
+        .unwrap_or("?");
+    let line = format!(
+        "ecall {}({}) = {} [pc={:#x} frame={}]",
+        name,
+        shown.join(", "),
+        result,
+        pc,
+        frame
+    );
+    writeln!(out.borrow_mut(), "{}", line).unwrap();
+}
+
+fn show_word(word: Option<i32>) -> String {
+    match word {
+        Some(word) => word.to_string(),
+        None => "?".to_string(),
+    }
+}
+
+fn show_addr(word: Option<i32>) -> String {
+    match word {
+        Some(word) => format!("{:#x}", word),
+        None => "?".to_string(),
+    }
+}
+
+fn show_words(m: &Machine, ptr: Option<i32>, len: Option<i32>) -> String {
+    let (ptr, len) = match (ptr, len) {
+        (Some(ptr), Some(len)) if len >= 0 => (ptr, len),
+        _ => return "?".to_string(),
+    };
+    let data: Option<Vec<u8>> = shown_range(ptr, len).and_then(|range| {
+        range
+            .map(|addr| m.peek(addr).map(|word| word as u8))
+            .collect()
+    });
+    show_data(data, len)
+}
+
+fn show_bytes(m: &Machine, ptr: Option<i32>, len: Option<i32>) -> String {
+    let (ptr, len) = match (ptr, len) {
+        (Some(ptr), Some(len)) if len >= 0 => (ptr, len),
+        _ => return "?".to_string(),
+    };
+    let data: Option<Vec<u8>> = shown_range(ptr, len).and_then(|range| {
+        range
+            .map(|byte_addr| m.peek_byte(byte_addr).map(|byte| byte as u8))
+            .collect()
+    });
+    show_data(data, len)
+}
+
+/// The addresses worth showing of `len` from `ptr`, or None if they run past i32::MAX.
+fn shown_range(ptr: i32, len: i32) -> Option<Range<i32>> {
+    Some(ptr..ptr.checked_add(len.min(MAX_SHOWN))?)
+}
+
+fn show_data(data: Option<Vec<u8>>, len: i32) -> String {
+    match data {
+        None => "<out of bounds>".to_string(),
+        Some(data) => {
+            let text: String = data.iter().map(|&b| escape_byte(b)).collect();
+            if len > MAX_SHOWN {
+                format!("\"{}\"...", text)
+            } else {
+                format!("\"{}\"", text)
+            }
+        }
+    }
+}
+
+fn escape_byte(b: u8) -> String {
+    match b {
+        b'\n' => "\\n".to_string(),
+        b'\t' => "\\t".to_string(),
+        b'"' | b'\\' => format!("\\{}", b as char),
+        0x20..=0x7e => (b as char).to_string(),
+        _ => format!("\\x{:02x}", b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_past_i32_max_are_out_of_bounds() {
+        let m = Machine::new();
+        for show in &[show_words, show_bytes] {
+            assert_eq!(show(&m, Some(i32::MAX - 1), Some(8)), "<out of bounds>");
+            assert_eq!(show(&m, Some(i32::MAX), Some(i32::MAX)), "<out of bounds>");
+            assert_eq!(show(&m, Some(0), Some(-1)), "?");
+        }
+    }
+}
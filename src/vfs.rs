@@ -0,0 +1,324 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::sandbox::Access;
+
+/// An open file on some filesystem.
+pub trait VfsFile: Read + Write + Seek {
+    fn try_clone(&self) -> io::Result<Box<dyn VfsFile>>;
+}
+
+/// Where guest file I/O ends up. Paths have already been checked by the sandbox.
+pub trait FileSystem {
+    /// Opens an existing file, for writing too if `access` allows it.
+    fn open(&mut self, path: &Path, access: Access) -> io::Result<Box<dyn VfsFile>>;
+
+    /// Whether paths refer to the host's disk, so the sandbox's root jail applies to them.
+    fn is_host(&self) -> bool;
+}
+
+impl VfsFile for File {
+    fn try_clone(&self) -> io::Result<Box<dyn VfsFile>> {
+        Ok(Box::new(File::try_clone(self)?))
+    }
+}
+
+#[derive(Default)]
+pub struct HostFs;
+
+impl FileSystem for HostFs {
+    fn open(&mut self, path: &Path, access: Access) -> io::Result<Box<dyn VfsFile>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(access == Access::ReadWrite)
+            .open(path)?;
+        Ok(Box::new(file))
+    }
+
+    fn is_host(&self) -> bool {
+        true
+    }
+}
+
+/// How large a guest may grow a file on a `MemFs`, so a runaway program can't take all the
+/// host's memory with one seek and write.
+pub const MAX_FILE_SIZE: usize = 64 << 20;
+
+struct MemFile {
+    data: Rc<RefCell<Vec<u8>>>,
+    pos: usize,
+    writable: bool,
+}
+
+impl Read for MemFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.data.borrow();
+        let start = self.pos.min(data.len());
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl Write for MemFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.writable {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        // write what fits, like a disk that's filling up
+        let n = buf.len().min(MAX_FILE_SIZE.saturating_sub(self.pos));
+        if n == 0 && !buf.is_empty() {
+            return Err(io::ErrorKind::FileTooLarge.into());
+        }
+        let buf = &buf[..n];
+        let mut data = self.data.borrow_mut();
+        let end = self.pos + buf.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[self.pos..end].copy_from_slice(buf);
+        self.pos = end;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let newpos = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+            SeekFrom::End(offset) => self.data.borrow().len() as i64 + offset,
+        };
+        if newpos < 0 {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        if newpos > MAX_FILE_SIZE as i64 {
+            return Err(io::ErrorKind::FileTooLarge.into());
+        }
+        self.pos = newpos as usize;
+        Ok(self.pos as u64)
+    }
+}
+
+impl VfsFile for MemFile {
+    fn try_clone(&self) -> io::Result<Box<dyn VfsFile>> {
+        Ok(Box::new(MemFile {
+            data: self.data.clone(),
+            pos: self.pos,
+            writable: self.writable,
+        }))
+    }
+}
+
+/// A filesystem that only exists for the run. Absolute and relative paths name the same file.
+#[derive(Default)]
+pub struct MemFs {
+    files: BTreeMap<PathBuf, Rc<RefCell<Vec<u8>>>>,
+}
+
+impl MemFs {
+    pub fn insert(&mut self, path: PathBuf, data: Vec<u8>) {
+        self.files.insert(path, Rc::new(RefCell::new(data)));
+    }
+
+    /// Copies in every file under `dir`, named by their path relative to it.
+    pub fn preload_dir(&mut self, dir: &Path) -> io::Result<()> {
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            for entry in fs::read_dir(current)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else {
+                    let name = path.strip_prefix(dir).unwrap().to_path_buf();
+                    self.insert(name, fs::read(&path)?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies in the files listed in a manifest, one `<guest path> <host path>` pair per line.
+    /// Blank lines and lines starting with `#` are skipped, host paths are relative to the manifest.
+    pub fn preload_manifest(&mut self, manifest: &Path) -> io::Result<()> {
+        let base = manifest.parent().unwrap_or_else(|| Path::new(""));
+        for line in fs::read_to_string(manifest)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let (guest, host) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(guest), Some(host), None) => (guest, host),
+                _ => {
+                    let msg = format!("bad manifest line: {}", line);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                }
+            };
+            let guest = guest.trim_start_matches('/');
+            self.insert(PathBuf::from(guest), fs::read(base.join(host))?);
+        }
+        Ok(())
+    }
+
+    /// Writes every file out under `dir`.
+    pub fn dump(&self, dir: &Path) -> io::Result<()> {
+        for (path, data) in self.files.iter() {
+            let dest = dir.join(path);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(dest, &*data.borrow())?;
+        }
+        Ok(())
+    }
+}
+
+impl FileSystem for MemFs {
+    fn open(&mut self, path: &Path, access: Access) -> io::Result<Box<dyn VfsFile>> {
+        let data = match self.files.get(path) {
+            Some(data) => data.clone(),
+            None => return Err(io::ErrorKind::NotFound.into()),
+        };
+        Ok(Box::new(MemFile {
+            data,
+            pos: 0,
+            writable: access == Access::ReadWrite,
+        }))
+    }
+
+    fn is_host(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(file: &mut Box<dyn VfsFile>) -> Vec<u8> {
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).unwrap();
+        data
+    }
+
+    fn fs_with(path: &str, data: &[u8]) -> MemFs {
+        let mut fs = MemFs::default();
+        fs.insert(PathBuf::from(path), data.to_vec());
+        fs
+    }
+
+    #[test]
+    fn open_finds_inserted_files_only() {
+        let mut fs = fs_with("a/b.txt", b"hello");
+        let mut file = fs.open(Path::new("a/b.txt"), Access::ReadOnly).unwrap();
+        assert_eq!(read_all(&mut file), b"hello");
+        let err = fs
+            .open(Path::new("a/c.txt"), Access::ReadOnly)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn writes_are_seen_by_other_opens() {
+        let mut fs = fs_with("f", b"abcdef");
+        let mut file = fs.open(Path::new("f"), Access::ReadWrite).unwrap();
+        file.seek(SeekFrom::Start(2)).unwrap();
+        file.write_all(b"XY").unwrap();
+        let mut other = fs.open(Path::new("f"), Access::ReadOnly).unwrap();
+        assert_eq!(read_all(&mut other), b"abXYef");
+    }
+
+    #[test]
+    fn read_only_files_refuse_writes() {
+        let mut fs = fs_with("f", b"abc");
+        let mut file = fs.open(Path::new("f"), Access::ReadOnly).unwrap();
+        let err = file.write(b"x").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(read_all(&mut file), b"abc");
+    }
+
+    #[test]
+    fn writing_past_the_end_fills_with_zeros() {
+        let mut fs = fs_with("f", b"ab");
+        let mut file = fs.open(Path::new("f"), Access::ReadWrite).unwrap();
+        assert_eq!(file.seek(SeekFrom::End(2)).unwrap(), 4);
+        file.write_all(b"z").unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(read_all(&mut file), b"ab\0\0z");
+        assert!(file.seek(SeekFrom::Current(-10)).is_err());
+    }
+
+    #[test]
+    fn files_stop_growing_at_the_cap() {
+        let mut fs = fs_with("f", b"");
+        let mut file = fs.open(Path::new("f"), Access::ReadWrite).unwrap();
+        let err = file
+            .seek(SeekFrom::Start(MAX_FILE_SIZE as u64 + 1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::FileTooLarge);
+
+        file.seek(SeekFrom::Start(MAX_FILE_SIZE as u64 - 2))
+            .unwrap();
+        assert_eq!(file.write(b"abcd").unwrap(), 2);
+        let err = file.write(b"cd").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::FileTooLarge);
+        assert_eq!(file.seek(SeekFrom::End(0)).unwrap(), MAX_FILE_SIZE as u64);
+    }
+
+    #[test]
+    fn clones_share_data_but_not_position() {
+        let mut fs = fs_with("f", b"abcd");
+        let mut file = fs.open(Path::new("f"), Access::ReadWrite).unwrap();
+        let mut buf = [0; 2];
+        file.read_exact(&mut buf).unwrap();
+        let mut clone = file.try_clone().unwrap();
+        file.write_all(b"CD").unwrap();
+        assert_eq!(read_all(&mut clone), b"CD");
+    }
+
+    #[test]
+    fn preload_manifest_and_dump() {
+        let dir = std::env::temp_dir().join(format!("nais-memfs-{}", std::process::id()));
+        fs::create_dir_all(dir.join("host")).unwrap();
+        fs::write(dir.join("host/in.txt"), b"data").unwrap();
+        fs::write(
+            dir.join("manifest"),
+            "# comment\n\n/guest/in.txt host/in.txt\n",
+        )
+        .unwrap();
+        let mut fs = MemFs::default();
+        fs.preload_manifest(&dir.join("manifest")).unwrap();
+        let mut file = fs
+            .open(Path::new("guest/in.txt"), Access::ReadOnly)
+            .unwrap();
+        assert_eq!(read_all(&mut file), b"data");
+
+        fs::write(dir.join("bad"), "one two three\n").unwrap();
+        let err = MemFs::default().preload_manifest(&dir.join("bad"));
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        fs.dump(&dir.join("out")).unwrap();
+        let dumped = fs::read(dir.join("out/guest/in.txt"));
+        let mut preloaded = MemFs::default();
+        let preload = preloaded.preload_dir(&dir.join("out"));
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(dumped.unwrap(), b"data");
+        preload.unwrap();
+        assert!(preloaded
+            .open(Path::new("guest/in.txt"), Access::ReadOnly)
+            .is_ok());
+    }
+}